@@ -0,0 +1,203 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use log::debug;
+use crate::{
+    build::ARGS,
+    build::build_dir_to_clean,
+    build::config,
+    config::Config,
+    content_type::handlers,
+    error::*,
+    report,
+};
+
+/// Lists every copy within the build directory, relative to it, so that copies can be told apart from anything else in there
+const MANIFEST: &str = ".jcake-ssg-assets";
+
+/// Whether the destination is an earlier copy of the source.
+/// Copies are given the modification time of their source, so a differing time or size means the source has changed since.
+fn unchanged(source: &Path, destination: &Path) -> Result<bool> {
+    let Ok(copy) = std::fs::metadata(destination) else {
+        return Ok(false);
+    };
+
+    let original = std::fs::metadata(source)?;
+    Ok(copy.len() == original.len() && copy.modified()? == original.modified()?)
+}
+
+fn copy_file(source: &Path, destination: &Path, modified: SystemTime) -> Result<()> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    debug!("Copying {:?} to {:?}", source, destination);
+    std::fs::copy(source, destination)?;
+    std::fs::OpenOptions::new().write(true).open(destination)?.set_modified(modified)?;
+
+    Ok(())
+}
+
+/// Copies every file within the roots which no content type claims into the build directory, keeping its path relative to its root.
+/// Files matching one of the config's ignore globs are skipped, as are files which haven't changed since they were last copied.
+/// Copies whose source has disappeared are removed. Returns the destination of every file copied or removed.
+pub fn copy(config: &Config, root: &Path, config_path: &Path) -> Result<Vec<PathBuf>> {
+    let claimed = handlers(config)?.iter()
+        .flat_map(|handler| handler.extensions())
+        .collect::<HashSet<_>>();
+
+    let patterns = std::iter::once("**/*".to_owned())
+        .chain(config.assets.ignore.iter().map(|pattern| format!("!{}", pattern)))
+        .collect::<Vec<_>>();
+
+    let root = root.canonicalize()?;
+    let build = root.join(&config.build);
+
+    let mut copied = Vec::new();
+    let mut expected = BTreeSet::new();
+
+    for dir in config.roots.iter() {
+        let dir = root.join(dir);
+        if !dir.exists() {
+            continue;
+        }
+
+        let dir = dir.canonicalize()?;
+        for file in globwalk::GlobWalkerBuilder::from_patterns(&dir, &patterns).file_type(globwalk::FileType::FILE).build()? {
+            let source = file?.into_path();
+
+            // The build directory may itself lie within a root
+            if source.starts_with(&build) || source.extension().is_some_and(|ext| claimed.contains(ext.to_string_lossy().as_ref())) {
+                continue;
+            }
+
+            let Ok(relative) = source.strip_prefix(&dir) else {
+                continue;
+            };
+
+            let destination = build.join(relative);
+            expected.insert(relative.to_path_buf());
+
+            if unchanged(&source, &destination)? {
+                continue;
+            }
+
+            copy_file(&source, &destination, std::fs::metadata(&source)?.modified()?)?;
+            copied.push(destination);
+        }
+    }
+
+    copied.extend(prune(&root, config_path, config, &expected)?);
+    Ok(copied)
+}
+
+/// Removes the copies listed in the build directory's manifest which are no longer `expected`, along with any directory they leave empty, then lists the `expected` copies in their place.
+/// Only files this has copied are ever removed, and nothing at all from a build directory which is, or contains, the site's own sources.
+fn prune(root: &Path, config_path: &Path, config: &Config, expected: &BTreeSet<PathBuf>) -> Result<Vec<PathBuf>> {
+    let build = match build_dir_to_clean(root, config_path, config) {
+        Ok(Some(build)) => build,
+        Ok(None) => return Ok(vec![]),
+        Err(Error::BuildError(err @ BuildError::UnsafeBuildDirectory { .. })) => {
+            report::warning(config_path, format!("copies whose source was removed are kept: {}", err.message()));
+            return Ok(vec![]);
+        },
+        Err(err) => return Err(err),
+    };
+
+    let manifest = build.join(MANIFEST);
+    let listed = std::fs::read_to_string(&manifest).unwrap_or_default();
+
+    let mut removed = Vec::new();
+
+    // Only paths within the build directory are listed, so anything else didn't come from here
+    for relative in listed.lines()
+        .map(PathBuf::from)
+        .filter(|relative| !expected.contains(relative) && relative.components().all(|component| matches!(component, Component::Normal(_)))) {
+        let path = build.join(relative);
+        if !path.is_file() {
+            continue;
+        }
+
+        debug!("Removing {:?}, as its source no longer exists", &path);
+        std::fs::remove_file(&path)?;
+
+        // Only empty directories can be removed, so directories which still hold anything are kept
+        for dir in path.ancestors().skip(1).take_while(|dir| *dir != build) {
+            if std::fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+
+        removed.push(path);
+    }
+
+    std::fs::write(&manifest, expected.iter().map(|relative| format!("{}\n", relative.to_string_lossy())).collect::<String>())?;
+
+    Ok(removed)
+}
+
+/// Copies the site's static assets into the build directory. See [`copy`].
+pub async fn copy_assets() -> Result<Vec<PathBuf>> {
+    let args = ARGS.get().expect("Args not set").clone();
+    let config = config();
+
+    tokio::task::spawn_blocking(move || copy(&config, &args.root, &args.config_path())).await?
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use crate::assets::copy;
+    use crate::config::Config;
+
+    #[test]
+    fn test_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+
+        for file in ["www/style.css", "www/img/logo.png", "www/about.en.html", "www/post.md", "www/txt.rn", "www/.about.en.html.swp", "www/.git/HEAD", "www/drafts/photo.png"] {
+            std::fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            std::fs::write(root.join(file), file).unwrap();
+        }
+
+        let mut config = toml::de::from_str::<Config>("roots = [\"www\"]\nlanguage = []\npage = []\nmarkdown = \"builtin\"\n[assets]\nignore = [\"drafts/**\", \"**/*.rn\", \"**/.*\", \"**/.*/**\"]").unwrap();
+        let copied = |config: &Config| {
+            let mut copied = copy(config, &root, &root.join("site.toml")).unwrap().into_iter()
+                .map(|path| path.strip_prefix(root.canonicalize().unwrap().join(&config.build)).unwrap().to_path_buf())
+                .collect::<Vec<_>>();
+            copied.sort();
+            copied
+        };
+
+        assert_eq!(copied(&config), [PathBuf::from("img/logo.png"), PathBuf::from("style.css")]);
+        assert!(copied(&config).is_empty());
+
+        std::fs::write(root.join("www/style.css"), "body {}").unwrap();
+        assert_eq!(copied(&config), [PathBuf::from("style.css")]);
+        assert_eq!(std::fs::read_to_string(root.join("build/style.css")).unwrap(), "body {}");
+
+        // Copies are removed along with their source, but nothing else in the build directory is
+        std::fs::write(root.join("build/about.html"), "").unwrap();
+        std::fs::write(root.join("build/notes.txt"), "").unwrap();
+        std::fs::remove_file(root.join("www/img/logo.png")).unwrap();
+        assert_eq!(copied(&config), [PathBuf::from("img/logo.png")]);
+        assert!(!root.join("build/img").exists());
+        assert!(root.join("build/about.html").exists());
+        assert!(root.join("build/notes.txt").exists());
+        assert!(root.join("build/style.css").exists());
+
+        // Nothing is removed from a build directory holding the site itself, even if the manifest lists it
+        std::fs::write(root.join("site.toml"), "").unwrap();
+        std::fs::write(root.join(".jcake-ssg-assets"), "site.toml\nwww/style.css\n").unwrap();
+        config.build = PathBuf::from(".");
+        assert!(copied(&config).is_empty());
+        assert!(root.join("site.toml").exists());
+        assert!(root.join("www/style.css").exists());
+        config.build = PathBuf::from("build");
+
+        // The build directory is never copied into itself
+        std::fs::remove_dir_all(root.join("build")).unwrap();
+        config.roots = vec![PathBuf::from(".")];
+        assert!(!copied(&config).iter().any(|path| path.starts_with("build")));
+    }
+}
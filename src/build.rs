@@ -1,84 +1,474 @@
-use std::sync::Arc;
-use std::sync::OnceLock;
-use std::path::PathBuf;
-use log::{debug, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::path::{Path, PathBuf};
+use log::debug;
+use tokio::task::JoinSet;
 use crate::{
-    parse::ParsingContext,
+    SITE_ROOT,
+    assets::copy_assets,
+    lint::lint_all,
     error::*,
     config::Config,
     config::Args,
-    config::ContentType,
+    config::Discovery,
+    content_type::Handler,
+    content_type::handlers,
     config::LanguageConfig,
-    error::*,
-    template::elements::Element
+    report,
 };
 use crate::template::elements::from_element;
+use crate::template::RenderContext;
+use crate::template::registry::Registry;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PageResolver {
     pub path: PathBuf,
+    pub name: String,
+    pub title: Option<String>,
     pub language: Arc<LanguageConfig>,
-    pub content_type: Arc<ContentType>
+    pub handler: Handler
 }
 
+impl PageResolver {
+    /// Where the rendered page should be written to, relative to the build directory.
+    /// The page's name already mirrors the layout of the source root it was found in, so it is simply nested under the language.
+    /// Pages in the default language are placed at the root of the build directory instead, if the config asks for it.
+    pub fn output_path(&self, config: &Config) -> PathBuf {
+        let file = PathBuf::from(format!("{}.html", &self.name));
+
+        if config.default_language_at_root && self.language.name == config.default_language {
+            return file;
+        }
+
+        PathBuf::from(&self.language.name).join(file)
+    }
+
+    /// Information about the page, exposed to scripts as `page`
+    pub fn info(&self) -> toml::Table {
+        let mut info = toml::Table::new();
+        info.insert("name".to_owned(), toml::Value::String(self.name.clone()));
+        info.insert("path".to_owned(), toml::Value::String(self.path.to_string_lossy().into_owned()));
+        info.insert("language".to_owned(), toml::Value::String(self.language.name.clone()));
+
+        if let Some(title) = &self.title {
+            info.insert("title".to_owned(), toml::Value::String(title.clone()));
+        }
+
+        info
+    }
+}
 
 pub static ARGS: OnceLock<Arc<Args>> = OnceLock::new();
-pub static CONFIG: OnceLock<Arc<Config>> = OnceLock::new();
+/// The config and registry are replaced whenever the site is reloaded, so they are only ever read through `config()` and `registry()`
+static CONFIG: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+static REGISTRY: RwLock<Option<Arc<Registry>>> = RwLock::new(None);
+
+pub fn config() -> Arc<Config> {
+    CONFIG.read().unwrap().clone().expect("Config not set")
+}
+
+pub fn registry() -> Arc<Registry> {
+    REGISTRY.read().unwrap().clone().expect("Registry not set")
+}
+
+/// Reads the site config, its scripts and the template directories, replacing whatever was loaded before
+pub async fn load() -> Result<()> {
+    let args = ARGS.get().expect("Args not set").clone();
+    let path = SITE_ROOT.get().expect("Site root not set").clone();
+
+    let mut config = toml::de::from_str::<Config>(&tokio::fs::read_to_string(path.as_path()).await?)?;
+    config.load_scripts(path.parent().expect("Invalid site root")).await?;
+
+    let registry = Registry::scan(config.templates.iter().map(|dir| args.root.join(dir))).await?;
+
+    *CONFIG.write().unwrap() = Some(Arc::new(config));
+    *REGISTRY.write().unwrap() = Some(Arc::new(registry));
+
+    Ok(())
+}
+
+/// The languages to build: every language of the config, unless `--language` narrows them down, without those passed to `--exclude-language`.
+/// Naming a language the config doesn't define is an error.
+pub fn languages(config: &Config, args: &Args) -> Result<Vec<Arc<LanguageConfig>>> {
+    if let Some(unknown) = args.languages.iter()
+        .chain(args.exclude_languages.iter())
+        .find(|lang| !config.languages.iter().any(|i| &i.name == *lang)) {
+        return Err(Error::BuildError(BuildError::UnknownLanguage(unknown.clone())));
+    }
+
+    Ok(config.languages.iter()
+        .filter(|i| args.languages.is_empty() || args.languages.contains(&i.name))
+        .filter(|i| !args.exclude_languages.contains(&i.name))
+        .cloned()
+        .collect())
+}
+
+/// Infers a page's name, language and content type from its path relative to the root it was found in, as in `blog/post.de.md`.
+/// Files without a language marker belong to the default language. Returns `None` for files no content type handles.
+fn classify(relative: &Path, config: &Config, handlers: &[Handler]) -> Option<(String, String, Handler)> {
+    let file = relative.file_name()?.to_str()?;
+    let (stem, ext) = file.rsplit_once('.')?;
+    let handler = handlers.iter().find(|handler| handler.extensions().iter().any(|i| i == ext))?;
+
+    let (stem, language) = match stem.rsplit_once('.') {
+        Some((stem, language)) if config.languages.iter().any(|i| i.name == language) => (stem, language),
+        _ => (stem, config.default_language.as_str()),
+    };
+
+    let name = relative.with_file_name(stem)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Some((name, language.to_owned(), handler.clone()))
+}
+
+/// Reports several files claiming to be the same page in the same language, none of which are built
+fn ambiguous(files: &[PathBuf]) {
+    report::warning(&files[0], format!("ambiguous page name: {} files claim to be the same page, so none of them are built: {}", files.len(), files.iter()
+        .map(|file| display_path(file))
+        .collect::<Vec<_>>()
+        .join(", ")));
+}
+
+/// Finds pages by walking the roots, rather than looking up each `[[page]]`
+fn discover(config: &Config, discovery: &Discovery, root: &Path, languages: &[Arc<LanguageConfig>]) -> Result<Vec<PageResolver>> {
+    let handlers = handlers(config)?;
+    let patterns = discovery.include.iter()
+        .cloned()
+        .chain(discovery.exclude.iter().map(|pattern| format!("!{}", pattern)))
+        .collect::<Vec<_>>();
+
+    let mut found = BTreeMap::<(String, String), Vec<PageResolver>>::new();
+
+    for dir in config.roots.iter() {
+        let dir = root.join(dir);
+        if !dir.exists() {
+            continue;
+        }
+
+        let dir = dir.canonicalize()?;
+        for file in globwalk::GlobWalkerBuilder::from_patterns(&dir, &patterns).file_type(globwalk::FileType::FILE).build()? {
+            let path = file?.into_path();
+            let Some((name, language, handler)) = path.strip_prefix(&dir).ok().and_then(|relative| classify(relative, config, &handlers)) else {
+                continue;
+            };
+
+            let Some(language) = languages.iter().find(|i| i.name == language) else {
+                continue;
+            };
+
+            found.entry((name.clone(), language.name.clone())).or_default().push(PageResolver {
+                title: config.pages.iter().find(|i| i.name == name).and_then(|i| i.title.clone()),
+                language: language.clone(),
+                handler,
+                name,
+                path,
+            });
+        }
+    }
+
+    Ok(found.into_values()
+        .filter_map(|mut potential| {
+            if potential.len() > 1 {
+                ambiguous(&potential.iter().map(|i| i.path.clone()).collect::<Vec<_>>());
+                return None;
+            }
+
+            potential.pop()
+        })
+        .collect())
+}
 
 pub async fn list_pages() -> Result<impl Iterator<Item=PageResolver>> {
     let args = ARGS.get().expect("Args not set").clone();
-    let config = CONFIG.get().expect("Config not set").clone();
+    let config = config();
+
+    if let Some(discovery) = &config.discovery {
+        return Ok(discover(&config, discovery, &args.root, &languages(&config, &args)?)?.into_iter());
+    }
 
     let mut pages = Vec::new();
 
-    for lang in args.languages.iter() {
-        if let Some(language) = config.languages.iter().find(|i| i.name.eq(lang)) {
-            for page in config.pages.iter() {
-                for content_type in config.content_types.iter() {
-                    for ext in content_type.extensions.iter() {
-                        let mut potential = Vec::with_capacity(10);
-
-                        for i in config.roots.iter()
-                            .map(|root| Result::<PathBuf>::Ok(args.root
-                                .canonicalize()?
-                                .join(root)
-                                .join(&page.name)
-                                .with_extension(format!("{}.{}", &language.name, ext)))) {
-
-                            let page = i?;
+    for language in languages(&config, &args)? {
+        for page in config.pages.iter() {
+            for handler in handlers(&config)? {
+                for ext in handler.extensions().iter() {
+                    let mut potential = Vec::with_capacity(10);
+
+                    // Files without a language marker belong to the default language
+                    let mut extensions = vec![format!("{}.{}", &language.name, ext)];
+                    if language.name == config.default_language {
+                        extensions.push(ext.clone());
+                    }
+
+                    for root in config.roots.iter() {
+                        let base = args.root.canonicalize()?.join(root).join(&page.name);
+
+                        for extension in extensions.iter() {
+                            let page = base.with_extension(extension);
                             if tokio::fs::try_exists(&page).await? {
                                 potential.push(page)
                             }
                         }
+                    }
 
-                        if potential.len() > 1 {
-                            warn!("Ambiguous page name: {:?}", &potential);
-                        } else if let Some(first) = potential.first() {
-                            pages.push(PageResolver {
-                                content_type: content_type.clone(),
-                                language: language.clone(),
-                                path: first.clone()
-                            });
-                        }
+                    if potential.len() > 1 {
+                        ambiguous(&potential);
+                    } else if let Some(first) = potential.first() {
+                        pages.push(PageResolver {
+                            handler: handler.clone(),
+                            language: language.clone(),
+                            name: page.name.clone(),
+                            title: page.title.clone(),
+                            path: first.clone()
+                        });
                     }
                 }
             }
-        } else {
-            warn!("No language '{}' defined", lang);
         }
     }
 
     Ok(pages.into_iter())
 }
 
-pub async fn build(page: PageResolver) -> Result<()> {
+/// Renders the page, returning its HTML along with every file it was built from.
+/// Content types, included files and scripts are all handled synchronously, so rendering happens on the blocking pool.
+pub async fn render(page: &PageResolver) -> Result<(String, HashSet<PathBuf>)> {
+    let config = config();
+    let registry = registry();
+
     let source = tokio::fs::read_to_string(&page.path).await?;
+    let page = page.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let document = page.handler.load(&page, source)?;
+        let mut cx = RenderContext::new(&page, config, registry.clone());
+        cx.front_matter(document.front_matter);
+
+        let html = from_element(&document.root, &registry).render(&mut cx, 0)?;
+
+        Ok((html, cx.dependencies))
+    }).await?
+}
+
+/// Renders the page into the build directory, returning every file it was built from
+pub async fn build(page: PageResolver) -> Result<HashSet<PathBuf>> {
+    let args = ARGS.get().expect("Args not set").clone();
+    let config = config();
+
+    let (html, dependencies) = render(&page).await?;
+
+    let output = args.root.join(&config.build).join(page.output_path(&config));
+    if let Some(parent) = output.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    debug!("Writing {:?} to {:?}", &page.path, &output);
+    tokio::fs::write(&output, html).await?;
+
+    Ok(dependencies)
+}
+
+/// The files each page was built from, by the page's source
+pub type Dependencies = HashMap<PathBuf, HashSet<PathBuf>>;
+
+/// Builds the given pages concurrently, returning the outcome of each by the page's source.
+/// If `fail_fast` is set, the first failure is returned as soon as it happens, and the pages still building are abandoned.
+pub async fn build_pages(pages: impl Iterator<Item=PageResolver>, fail_fast: bool) -> Result<Vec<(PathBuf, Result<HashSet<PathBuf>>)>> {
+    let mut set = JoinSet::new();
+
+    for page in pages {
+        set.spawn(async move { (page.path.clone(), build(page).await) });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = set.join_next().await {
+        match result? {
+            (_, Err(err)) if fail_fast => return Err(err),
+            result => results.push(result),
+        }
+    }
+
+    Ok(results)
+}
+
+/// Builds every page and copies the static assets. Pages which fail to build are reported, and left out of the dependencies.
+/// With `--fail-fast`, the first failure is returned instead.
+pub async fn build_all() -> Result<Dependencies> {
+    let args = ARGS.get().expect("Args not set").clone();
+    let mut dependencies = Dependencies::new();
+
+    copy_assets().await?;
+
+    for (page, result) in build_pages(list_pages().await?, args.fail_fast).await? {
+        match result {
+            Ok(page_dependencies) => { dependencies.insert(page, page_dependencies); },
+            Err(err) => report::error(page, &err),
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// Lints and renders every page without writing any of them, reporting each problem found and each page which fails to build.
+/// With `--fail-fast`, the first failure is returned instead.
+pub async fn check_all() -> Result<()> {
+    let args = ARGS.get().expect("Args not set").clone();
+    let mut set = JoinSet::new();
+
+    // Ambiguous pages are reported while listing them, so they are only listed once
+    let pages = list_pages().await?.collect::<Vec<_>>();
+    lint_all(&pages).await?;
+
+    for page in pages {
+        set.spawn(async move { (render(&page).await, page.path) });
+    }
+
+    while let Some(result) = set.join_next().await {
+        match result? {
+            (Ok(_), _) => {},
+            (Err(err), _) if args.fail_fast => return Err(err),
+            (Err(err), page) => report::error(page, &err),
+        }
+    }
+
+    Ok(())
+}
 
-    let mut cx = ParsingContext::new(source, page.path.clone())?;
-    let page = cx.parse()?;
-    let el = from_element(&page);
+/// Removes the build directory
+pub async fn clean() -> Result<()> {
+    let args = ARGS.get().expect("Args not set").clone();
 
-    debug!("Transform: {:?}", el.render(0));
+    if let Some(build) = build_dir_to_clean(&args.root, &args.config_path(), &config())? {
+        debug!("Removing {:?}", &build);
+        tokio::fs::remove_dir_all(&build).await?;
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// The canonical build directory, if it exists. A build directory which is, or contains, the site root, its config or any of its roots or template directories is refused.
+pub(crate) fn build_dir_to_clean(root: &Path, config_path: &Path, config: &Config) -> Result<Option<PathBuf>> {
+    let root = root.canonicalize()?;
+    let build = match root.join(&config.build).canonicalize() {
+        Ok(build) => build,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // Directories which don't exist yet can't be removed, but are still compared as they would be
+    let protected = std::iter::once(root.clone())
+        .chain(std::iter::once(config_path.to_owned()))
+        .chain(config.roots.iter().chain(config.templates.iter()).map(|dir| root.join(dir)))
+        .map(|path| path.canonicalize().unwrap_or(path));
+
+    for path in protected {
+        if path.starts_with(&build) {
+            return Err(Error::BuildError(BuildError::UnsafeBuildDirectory { build, contains: path }));
+        }
+    }
+
+    Ok(Some(build))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use clap::Parser;
+    use std::path::PathBuf;
+    use std::path::Path;
+    use crate::build::{PageResolver, build_dir_to_clean, classify, languages};
+    use crate::config::{Args, Config, LanguageConfig};
+    use crate::content_type::Handler;
+    use crate::error::*;
+
+    #[test]
+    fn test_languages() {
+        let language = |name: &str| Arc::new(LanguageConfig { name: name.to_owned(), ..LanguageConfig::default() });
+        let config = Config {
+            languages: vec![language("en"), language("de"), language("fr")],
+            ..Config::default()
+        };
+
+        let names = |args: &[&str]| languages(&config, &Args::parse_from(std::iter::once("jcake-ssg").chain(args.iter().copied())))
+            .map(|languages| languages.iter().map(|i| i.name.clone()).collect::<Vec<_>>());
+
+        assert_eq!(names(&[]).unwrap(), ["en", "de", "fr"]);
+        assert_eq!(names(&["-l", "de", "-l", "en"]).unwrap(), ["en", "de"]);
+        assert_eq!(names(&["build", "-l", "fr,de"]).unwrap(), ["de", "fr"]);
+        assert_eq!(names(&["-x", "fr", "-x", "de"]).unwrap(), ["en"]);
+        assert_eq!(names(&["--exclude-language", "de"]).unwrap(), ["en", "fr"]);
+        assert!(matches!(names(&["-l", "es"]), Err(Error::BuildError(BuildError::UnknownLanguage(lang))) if lang == "es"));
+        assert!(matches!(names(&["-x", "es"]), Err(Error::BuildError(BuildError::UnknownLanguage(_)))));
+    }
+
+    #[test]
+    fn test_output_path() {
+        let page = |language: &str| PageResolver {
+            path: PathBuf::from("www/blog/post.html"),
+            name: "blog/post".to_owned(),
+            title: None,
+            language: Arc::new(LanguageConfig { name: language.to_owned(), ..LanguageConfig::default() }),
+            handler: Handler::Native,
+        };
+
+        let mut config = Config { default_language: "en".to_owned(), ..Config::default() };
+        assert_eq!(page("en").output_path(&config), PathBuf::from("en/blog/post.html"));
+
+        config.default_language_at_root = true;
+        assert_eq!(page("en").output_path(&config), PathBuf::from("blog/post.html"));
+        assert_eq!(page("de").output_path(&config), PathBuf::from("de/blog/post.html"));
+    }
+
+    #[test]
+    fn test_classify() {
+        let language = |name: &str| Arc::new(LanguageConfig { name: name.to_owned(), ..LanguageConfig::default() });
+        let config = Config {
+            default_language: "en".to_owned(),
+            languages: vec![language("en"), language("de")],
+            ..Config::default()
+        };
+
+        let handlers = [Handler::Native, Handler::Markdown];
+        let classify = |path: &str| classify(Path::new(path), &config, &handlers)
+            .map(|(name, language, handler)| (name, language, handler.extensions()[0].clone()));
+
+        assert_eq!(classify("about.html"), Some(("about".to_owned(), "en".to_owned(), "html".to_owned())));
+        assert_eq!(classify("blog/post.de.md"), Some(("blog/post".to_owned(), "de".to_owned(), "md".to_owned())));
+        assert_eq!(classify("v1.2.html"), Some(("v1.2".to_owned(), "en".to_owned(), "html".to_owned())));
+        assert_eq!(classify("style.css"), None);
+        assert_eq!(classify("README"), None);
+    }
+
+    #[test]
+    fn test_build_dir_to_clean() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        for dir in ["www", "include", "build", "out/nested"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("site.toml"), "").unwrap();
+
+        let clean = |build: &str| {
+            let config = Config {
+                roots: vec!["www".into()],
+                templates: vec!["include".into()],
+                build: build.into(),
+                ..Config::default()
+            };
+
+            build_dir_to_clean(&root, &root.join("site.toml"), &config)
+        };
+
+        let root = root.canonicalize().unwrap();
+        assert_eq!(clean("build").unwrap(), Some(root.join("build")));
+        assert_eq!(clean("out/nested").unwrap(), Some(root.join("out/nested")));
+        assert_eq!(clean("missing").unwrap(), None);
+
+        for (build, contains) in [(".", root.clone()), ("..", root.clone()), ("www", root.join("www")), ("include/", root.join("include")), ("build/..", root.clone())] {
+            assert!(matches!(clean(build), Err(Error::BuildError(BuildError::UnsafeBuildDirectory { contains: path, .. })) if path == contains), "{}", build);
+        }
+    }
+}
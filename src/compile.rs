@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use rune::{Any, Context, ContextError, Diagnostics, Module, Source, Sources, Unit, Vm};
+use rune::diagnostics::Diagnostic;
+use rune::runtime::{RuntimeContext, Value};
+use serde::Deserialize;
+use crate::{
+    config::LanguageConfig,
+    content_type,
+    error::*,
+    parse::Origin,
+    template::RenderContext,
+    template::Variable,
+};
+
+/// Markup which has already been rendered, and must therefore not be escaped again when emitted.
+#[derive(Debug, Clone, Any)]
+#[rune(item = ::jcake_ssg)]
+pub struct Markup(pub String);
+
+/// The result of evaluating an expression
+pub enum Output {
+    Text(String),
+    Markup(String),
+}
+
+struct Runtime {
+    context: Context,
+    runtime: Arc<RuntimeContext>,
+    units: Mutex<HashMap<String, Arc<Unit>>>,
+}
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// How many compiled units are kept before the cache is emptied
+const CACHE_LIMIT: usize = 1024;
+
+/// Variable names which are always in scope. `vars` holds every other variable by its name as written.
+const GLOBALS: [&str; 4] = ["page", "language", "config", "vars"];
+
+/// Rune's keywords, which can't be used as parameter names
+const KEYWORDS: [&str; 52] = [
+    "abstract", "alignof", "as", "async", "await", "become", "break", "const", "continue", "crate", "do", "else", "enum", "extern",
+    "false", "final", "fn", "for", "if", "impl", "in", "is", "let", "loop", "macro", "match", "mod", "move", "not", "offsetof",
+    "override", "priv", "proc", "pub", "pure", "ref", "return", "select", "Self", "self", "sizeof", "static", "struct", "super",
+    "true", "type", "typeof", "unsafe", "use", "virtual", "while", "yield",
+];
+
+fn module() -> core::result::Result<Module, ContextError> {
+    let mut module = Module::with_crate("jcake_ssg")?;
+
+    module.ty::<Markup>()?;
+    module.ty::<LanguageConfig>()?;
+    module.ty::<Origin>()?;
+
+    Ok(module)
+}
+
+fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        let mut context = Context::with_default_modules().expect("Failed to create Rune context");
+        context.install(module().expect("Failed to build Rune module")).expect("Failed to install Rune module");
+        context.install(content_type::module().expect("Failed to build Rune module")).expect("Failed to install Rune module");
+
+        Runtime {
+            runtime: Arc::new(context.runtime().expect("Failed to create Rune runtime")),
+            context,
+            units: Mutex::new(HashMap::new()),
+        }
+    })
+}
+
+fn rune_error<Message: ToString>(origin: &Origin, message: Message) -> Error {
+    Error::RuneError(RuneError {
+        origin: origin.clone(),
+        message: message.to_string(),
+    })
+}
+
+/// Compiles a script. Units are cached by their source, so identical scripts are only compiled once while the cache has room.
+pub fn compile<Script: AsRef<str>>(script: Script, origin: &Origin) -> Result<Arc<Unit>> {
+    let runtime = runtime();
+
+    if let Some(unit) = runtime.units.lock().unwrap().get(script.as_ref()) {
+        return Ok(unit.clone());
+    }
+
+    let mut sources = Sources::new();
+    sources.insert(Source::new(origin.source.to_string_lossy(), script.as_ref()).map_err(|err| rune_error(origin, err))?)
+        .map_err(|err| rune_error(origin, err))?;
+
+    let mut diagnostics = Diagnostics::new();
+    let unit = rune::prepare(&mut sources)
+        .with_context(&runtime.context)
+        .with_diagnostics(&mut diagnostics)
+        .build()
+        .map_err(|_| rune_error(origin, diagnostics.diagnostics()
+            .iter()
+            .filter_map(|diagnostic| match diagnostic {
+                Diagnostic::Fatal(fatal) => Some(fatal.to_string()),
+                _ => None
+            })
+            .collect::<Vec<_>>()
+            .join("\n")))?;
+
+    let unit = Arc::new(unit);
+    let mut units = runtime.units.lock().unwrap();
+
+    // Scripts change as pages are edited while serving, so old units are dropped rather than kept forever
+    if units.len() >= CACHE_LIMIT {
+        units.clear();
+    }
+
+    units.insert(script.as_ref().to_owned(), unit.clone());
+
+    Ok(unit)
+}
+
+/// Creates a virtual machine for the given unit
+pub fn vm(unit: Arc<Unit>) -> Vm {
+    Vm::new(runtime().runtime.clone(), unit)
+}
+
+/// Converts a variable into a value the VM understands
+fn to_value(variable: &Variable, origin: &Origin) -> Result<Value> {
+    match variable {
+        Variable::Value(value) => Value::deserialize(value.clone()).map_err(|err| rune_error(origin, err)),
+        Variable::Markup(markup) => rune::to_value(Markup(markup.clone())).map_err(|err| rune_error(origin, err)),
+    }
+}
+
+/// The config as a value the VM understands. It is only converted once per page.
+fn config(origin: &Origin, cx: &RenderContext) -> Result<Value> {
+    if let Some(config) = cx.config_value.get() {
+        return Ok(config.clone());
+    }
+
+    let config = Value::deserialize(toml::Value::try_from(&*cx.config).map_err(|err| rune_error(origin, err))?).map_err(|err| rune_error(origin, err))?;
+    Ok(cx.config_value.get_or_init(|| config).clone())
+}
+
+/// Whether the name can be used as a Rune identifier. Hyphens are permitted, as they are replaced with underscores.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars.next().is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-')
+}
+
+/// Runs an expression against the current scope, returning the VM it ran in alongside its result.
+/// The expression becomes the body of a function which receives every variable in scope through `vars`,
+/// and those whose names are usable as identifiers as arguments of their own.
+fn run<Expr: AsRef<str>>(expression: Expr, origin: &Origin, cx: &RenderContext) -> Result<(Vm, Value)> {
+    let mut variables = cx.variables()
+        .into_iter()
+        .filter(|(name, _)| !GLOBALS.contains(name))
+        .collect::<Vec<_>>();
+
+    variables.sort_by_key(|(name, _)| *name);
+
+    // `data-x` and `data_x` would both become `data_x`, so neither is passed on its own
+    let parameter = |name: &str| name.replace('-', "_");
+    let parameters = variables.iter()
+        .filter(|(name, _)| is_identifier(name) && !KEYWORDS.contains(&parameter(name).as_str()))
+        .filter(|(name, _)| variables.iter().all(|(other, _)| other == name || parameter(other) != parameter(name)))
+        .collect::<Vec<_>>();
+
+    let signature = GLOBALS.into_iter()
+        .map(ToOwned::to_owned)
+        .chain(parameters.iter().map(|(name, _)| parameter(name)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let script = format!("pub fn main({}) {{\n{}\n}}\n\npub fn display(value) {{\n    `${{value}}`\n}}\n", signature, expression.as_ref());
+    let mut vm = vm(compile(script, origin)?);
+
+    let mut args = vec![
+        Value::deserialize(toml::Value::Table(cx.page.clone())).map_err(|err| rune_error(origin, err))?,
+        rune::to_value((*cx.language).clone()).map_err(|err| rune_error(origin, err))?,
+        config(origin, cx)?,
+    ];
+
+    let mut vars = HashMap::new();
+    for (name, variable) in &variables {
+        vars.insert(name.to_string(), to_value(variable, origin)?);
+    }
+    args.push(rune::to_value(vars).map_err(|err| rune_error(origin, err))?);
+
+    for (_, variable) in parameters {
+        args.push(to_value(variable, origin)?);
+    }
+
+    let value = vm.execute(["main"], args)
+        .and_then(|mut execution| execution.complete().into_result())
+        .map_err(|err| rune_error(origin, err))?;
+
+    Ok((vm, value))
+}
+
+/// Evaluates an expression into content
+pub fn evaluate<Expr: AsRef<str>>(expression: Expr, origin: &Origin, cx: &RenderContext) -> Result<Output> {
+    let (mut vm, value) = run(expression, origin, cx)?;
+
+    Ok(match value {
+        Value::EmptyTuple => Output::Text(String::new()),
+        Value::String(string) => Output::Text(string.borrow_ref().map_err(|err| rune_error(origin, err))?.to_string()),
+        Value::Option(option) if option.borrow_ref().map_err(|err| rune_error(origin, err))?.is_none() => Output::Text(String::new()),
+        value => match rune::from_value::<Markup>(value.clone()) {
+            Ok(Markup(markup)) => Output::Markup(markup),
+            Err(_) => {
+                let display = vm.call(["display"], (value,)).map_err(|err| rune_error(origin, err))?;
+                Output::Text(rune::from_value::<String>(display).map_err(|err| rune_error(origin, err))?)
+            }
+        }
+    })
+}
+
+/// Evaluates an expression as a condition. Besides booleans, empty values such as `()`, `None`, `0` and empty strings or vectors are false.
+pub fn truthy<Expr: AsRef<str>>(expression: Expr, origin: &Origin, cx: &RenderContext) -> Result<bool> {
+    let (_, value) = run(expression, origin, cx)?;
+
+    Ok(match value {
+        Value::Bool(bool) => bool,
+        Value::EmptyTuple => false,
+        Value::Integer(integer) => integer != 0,
+        Value::Float(float) => float != 0.0,
+        Value::String(string) => !string.borrow_ref().map_err(|err| rune_error(origin, err))?.is_empty(),
+        Value::Vec(vec) => !vec.borrow_ref().map_err(|err| rune_error(origin, err))?.is_empty(),
+        Value::Option(option) => option.borrow_ref().map_err(|err| rune_error(origin, err))?.is_some(),
+        _ => true,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use crate::compile::{evaluate, truthy, Output};
+    use crate::parse::Origin;
+    use crate::template::{RenderContext, Variable};
+
+    fn origin() -> Origin {
+        Origin { source: PathBuf::from("test.html"), offset: 0, depth: 0, token_length: 0 }
+    }
+
+    fn text(output: Output) -> String {
+        match output {
+            Output::Text(text) => text,
+            Output::Markup(markup) => panic!("Expected text, found markup {:?}", markup),
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut cx = RenderContext::default();
+        cx.page.insert("name".to_owned(), toml::Value::String("index".to_owned()));
+        cx.scope.push([
+            ("greeting".to_owned(), Variable::Value(toml::Value::String("Hello".to_owned()))),
+            ("body".to_owned(), Variable::Markup("<p>Hi</p>".to_owned())),
+        ].into_iter().collect());
+
+        assert_eq!(text(evaluate("1 + 2", &origin(), &cx).unwrap()), "3");
+        assert_eq!(text(evaluate("`${greeting}, world`", &origin(), &cx).unwrap()), "Hello, world");
+        assert_eq!(text(evaluate("page.name", &origin(), &cx).unwrap()), "index");
+        assert!(matches!(evaluate("body", &origin(), &cx).unwrap(), Output::Markup(markup) if markup == "<p>Hi</p>"));
+        assert!(evaluate("undefined_variable", &origin(), &cx).is_err());
+
+        assert!(truthy("greeting == \"Hello\"", &origin(), &cx).unwrap());
+        assert!(!truthy("None", &origin(), &cx).unwrap());
+        assert!(!truthy("\"\"", &origin(), &cx).unwrap());
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        let mut cx = RenderContext::default();
+        cx.scope.push([
+            ("for".to_owned(), Variable::Value(toml::Value::String("label".to_owned()))),
+            ("data-x".to_owned(), Variable::Value(toml::Value::Integer(1))),
+            ("data_x".to_owned(), Variable::Value(toml::Value::Integer(2))),
+            ("aria-label".to_owned(), Variable::Value(toml::Value::String("Close".to_owned()))),
+        ].into_iter().collect());
+
+        // Keywords and names which collide once hyphens are replaced are only reachable through `vars`
+        assert_eq!(text(evaluate("1 + 2", &origin(), &cx).unwrap()), "3");
+        assert_eq!(text(evaluate("vars[\"for\"]", &origin(), &cx).unwrap()), "label");
+        assert_eq!(text(evaluate("vars[\"data-x\"] + vars[\"data_x\"]", &origin(), &cx).unwrap()), "3");
+        assert!(evaluate("data_x", &origin(), &cx).is_err());
+        assert_eq!(text(evaluate("aria_label", &origin(), &cx).unwrap()), "Close");
+    }
+}
@@ -1,9 +1,11 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use clap::Parser;
+use clap::{ArgAction, Parser, Subcommand};
 use rune::{Any};
 use serde::Deserialize;
 use serde::Serialize;
+use crate::error::*;
+use crate::report::Format;
 
 #[inline]
 fn default_language() -> String { "en".into() }
@@ -11,6 +13,15 @@ fn default_language() -> String { "en".into() }
 #[inline]
 fn default_build() -> PathBuf { "build".into() }
 
+#[inline]
+fn default_templates() -> Vec<PathBuf> { vec!["include".into()] }
+
+#[inline]
+fn default_include() -> Vec<String> { vec!["**/*".into()] }
+
+#[inline]
+fn default_ignore() -> Vec<String> { vec!["**/*.rn".into(), "**/.*".into(), "**/.*/**".into()] }
+
 #[inline]
 fn default_content_type() -> Vec<Arc<ContentType>> {
     vec![]
@@ -18,14 +29,74 @@ fn default_content_type() -> Vec<Arc<ContentType>> {
 
 #[derive(Debug, Clone, Parser)]
 pub struct Args {
-    #[clap(long, short)]
+    /// The site config. Defaults to `site.toml` within the root.
+    #[clap(long, short, global = true)]
     pub config: Option<PathBuf>,
 
-    #[clap(long, short, default_value = "./")]
+    /// The directory containing the site
+    #[clap(long, short, default_value = "./", global = true)]
     pub root: PathBuf,
 
-    #[clap(long = "language", short, num_args(0..))]
-    pub languages: Vec<String>
+    /// Only build these languages, rather than every language of the config. May be repeated or comma-separated.
+    #[clap(long = "language", short, num_args(1), value_delimiter = ',', action = ArgAction::Append, global = true)]
+    pub languages: Vec<String>,
+
+    /// Don't build these languages. May be repeated or comma-separated.
+    #[clap(long = "exclude-language", short = 'x', num_args(1), value_delimiter = ',', action = ArgAction::Append, global = true)]
+    pub exclude_languages: Vec<String>,
+
+    /// Stop at the first page which fails to build, rather than reporting every failure once all pages have been attempted
+    #[clap(long, global = true)]
+    pub fail_fast: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// The site config, as given or within the root
+    pub fn config_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(|| self.root.join("site.toml"))
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Builds every page of the site
+    Build {
+        /// Keep running, rebuilding the pages affected by each change to the site's sources
+        #[clap(long, short)]
+        watch: bool,
+    },
+    /// Builds the site, then serves the build directory for previewing
+    #[cfg(feature = "serve")]
+    Serve {
+        #[clap(long, short, default_value_t = 8080)]
+        port: u16,
+
+        #[clap(long, default_value = "127.0.0.1")]
+        host: String,
+
+        /// Don't rebuild pages as their sources change, nor reload them in the browser
+        #[clap(long)]
+        no_reload: bool,
+    },
+    /// Creates a new site with a config, a page and a template
+    New {
+        /// The directory to create the site in. Defaults to the root.
+        path: Option<PathBuf>,
+    },
+    /// Parses, lints and renders every page without writing anything, reporting any problems
+    Check {
+        #[clap(long, value_enum, default_value_t = Format::Human)]
+        format: Format,
+
+        /// Fail if there are any warnings, not just errors
+        #[clap(long)]
+        deny_warnings: bool,
+    },
+    /// Removes the build directory
+    Clean,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -45,16 +116,76 @@ pub struct Config {
     #[serde(default = "default_build")]
     pub build: PathBuf,
 
+    #[serde(default = "default_templates")]
+    pub templates: Vec<PathBuf>,
+
     #[serde(rename = "content-type", default = "default_content_type")]
-    pub content_types: Vec<Arc<ContentType>>
+    pub content_types: Vec<Arc<ContentType>>,
+
+    #[serde(default)]
+    pub pretty: bool,
+
+    #[serde(default)]
+    pub markdown: MarkdownRenderer,
+
+    #[serde(default)]
+    pub assets: Assets,
+
+    /// If set, pages are found by walking the roots rather than by looking up each `[[page]]`
+    pub discovery: Option<Discovery>,
+
+    /// Whether pages in the default language are emitted at the root of the build directory, rather than under their language
+    #[serde(default)]
+    pub default_language_at_root: bool,
+}
+
+/// Which files within the roots are pages. A file is only a page if a content type handles its extension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Discovery {
+    /// Globs, relative to each root, of the files to consider
+    #[serde(default = "default_include")]
+    pub include: Vec<String>,
+    /// Globs of files to skip, even if they match `include`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// Files within the roots which no content type claims are static assets, copied into the build directory as they are
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Assets {
+    /// Globs, relative to each root, of files which are never copied. Defaults to scripts and hidden files.
+    #[serde(default = "default_ignore")]
+    pub ignore: Vec<String>,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self { ignore: default_ignore() }
+    }
+}
+
+/// Which renderer converts Markdown pages
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownRenderer {
+    /// `pandoc` if the feature is enabled, `builtin` otherwise
+    #[default]
+    Auto,
+    /// The `pandoc` executable
+    Pandoc,
+    /// A pure-Rust renderer supporting CommonMark, tables, footnotes and heading anchors
+    Builtin,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Any)]
+#[rune(item = ::jcake_ssg)]
 pub struct LanguageConfig {
     #[serde(rename = "abbreviation")]
+    #[rune(get)]
     pub name: String,
 
     #[serde(rename = "full-name")]
+    #[rune(get)]
     pub native: String,
 
     pub menu: Vec<Menu>,
@@ -75,11 +206,114 @@ pub enum Menu {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentType {
     pub extensions: Vec<String>,
-    pub handler: String // A Rune script
+    #[serde(flatten)]
+    pub script: Script,
+}
+
+/// A Rune script, given either inline with `handler` or as a file with `handler_path`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Script {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler_path: Option<PathBuf>,
+
+    /// The script's source, available once it has been loaded
+    #[serde(skip)]
+    pub source: String,
+}
+
+impl Script {
+    /// Reads the script from `handler_path`, resolved relative to the site root, unless it was given inline.
+    /// Exactly one of `handler` and `handler_path` must be set. `section` names the script in errors.
+    pub async fn load(&mut self, root: &Path, section: String) -> Result<()> {
+        self.source = match (&self.handler, &self.handler_path) {
+            (Some(handler), None) => handler.clone(),
+            (None, Some(path)) => {
+                let path = root.join(path);
+                tokio::fs::read_to_string(&path).await
+                    .map_err(|err| Error::IoError(std::io::Error::new(err.kind(), format!("{}: {:?}: {}", section, path, err))))?
+            },
+            (Some(_), Some(_)) => return Err(Error::BuildError(BuildError::AmbiguousScript(section))),
+            (None, None) => return Err(Error::BuildError(BuildError::MissingScript(section))),
+        };
+
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Every script defined in the config, along with the name of the section it was defined in
+    fn scripts_mut(&mut self) -> impl Iterator<Item=(String, &mut Script)> {
+        self.content_types.iter_mut()
+            .map(|content_type| {
+                let content_type = Arc::make_mut(content_type);
+                (format!("content-type [{}]", content_type.extensions.join(", ")), &mut content_type.script)
+            })
+    }
+
+    /// Loads every script the config refers to. Paths are resolved relative to `root`.
+    pub async fn load_scripts(&mut self, root: &Path) -> Result<()> {
+        for (section, script) in self.scripts_mut() {
+            script.load(root, section).await?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Page {
     pub name: String,
     pub title: Option<String>,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use clap::Parser;
+    use crate::config::{Args, Command, Config};
+    use crate::error::*;
+
+    fn load(root: &Path, config: &str) -> Result<Config> {
+        let mut config = toml::de::from_str::<Config>(&format!("roots = []\nlanguage = []\npage = []\n{}", config))?;
+        tokio::runtime::Runtime::new().unwrap().block_on(config.load_scripts(root))?;
+
+        Ok(config)
+    }
+
+    #[test]
+    fn test_load_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("content-types")).unwrap();
+        std::fs::write(dir.path().join("content-types/txt.rn"), "pub fn content_type(origin, page) {}").unwrap();
+        let load = |config: &str| load(dir.path(), config);
+
+        let inline = load("[[content-type]]\nextensions = [\"txt\"]\nhandler = \"// Inline\"").unwrap();
+        assert_eq!(inline.content_types[0].script.source, "// Inline");
+
+        let file = load("[[content-type]]\nextensions = [\"txt\"]\nhandler_path = \"content-types/txt.rn\"").unwrap();
+        assert_eq!(file.content_types[0].script.source, "pub fn content_type(origin, page) {}");
+
+        assert!(matches!(load("[[content-type]]\nextensions = [\"txt\"]\nhandler = \"\"\nhandler_path = \"content-types/txt.rn\""), Err(Error::BuildError(BuildError::AmbiguousScript(_)))));
+        assert!(matches!(load("[[content-type]]\nextensions = [\"txt\"]"), Err(Error::BuildError(BuildError::MissingScript(section))) if section == "content-type [txt]"));
+        assert!(matches!(load("[[content-type]]\nextensions = [\"txt\"]\nhandler_path = \"missing.rn\""), Err(Error::IoError(_))));
+    }
+
+    #[test]
+    fn test_language_args() {
+        let args = |args: &[&str]| Args::try_parse_from(std::iter::once("jcake-ssg").chain(args.iter().copied())).unwrap();
+
+        let build = args(&["-l", "en", "build"]);
+        assert_eq!(build.languages, ["en"]);
+        assert!(matches!(build.command, Some(Command::Build { watch: false })));
+
+        let check = args(&["--language", "de,fr", "check"]);
+        assert_eq!(check.languages, ["de", "fr"]);
+        assert!(matches!(check.command, Some(Command::Check { .. })));
+
+        let exclude = args(&["clean", "-x", "de", "-x", "fr,es"]);
+        assert_eq!(exclude.exclude_languages, ["de", "fr", "es"]);
+        assert!(matches!(exclude.command, Some(Command::Clean)));
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::path::Path;
+use crate::{
+    build::PageResolver,
+    content_type::Document,
+    content_type::element,
+    content_type::literal,
+    error::*,
+    parse::Origin,
+};
+
+/// The line fencing TOML front matter
+const FENCE: &str = "+++";
+
+/// Separates the front matter from the start of the source, if there is any.
+/// Front matter is TOML, placed between two `+++` lines. `path` is the file the source was read from, for errors.
+pub fn front_matter<'a>(path: &Path, source: &'a str) -> Result<(toml::Table, &'a str)> {
+    let Some(rest) = source.strip_prefix(FENCE).and_then(|rest| rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n"))) else {
+        return Ok((toml::Table::new(), source));
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == FENCE {
+            return Ok((toml::de::from_str(&rest[..offset])?, &rest[offset + line.len()..]));
+        }
+
+        offset += line.len();
+    }
+
+    // The opening fence is never closed
+    Err(Error::BuildError(BuildError::UnexpectedEOF(Origin {
+        source: path.to_path_buf(),
+        offset: 0,
+        depth: 0,
+        token_length: FENCE.len(),
+    })))
+}
+
+/// Wraps rendered markdown into a page. If the front matter names a `template` or `title`, they are passed to the page as if they were its attributes.
+pub fn document(page: &PageResolver, html: String, front_matter: toml::Table) -> Document {
+    let origin = Origin {
+        source: page.path.clone(),
+        offset: 0,
+        depth: 0,
+        token_length: html.len(),
+    };
+
+    let attributes = ["template", "title"].into_iter()
+        .filter_map(|name| match front_matter.get(name) {
+            Some(toml::Value::String(value)) => Some((name.to_owned(), value.clone())),
+            _ => None
+        })
+        .collect();
+
+    let body = element("escape", vec![("mode".to_owned(), "raw".to_owned())], vec![literal(html, &origin)], &origin);
+
+    Document {
+        root: match element("page", attributes, vec![body], &origin) {
+            crate::parse::Body::Element(root) => root,
+            _ => unreachable!()
+        },
+        front_matter,
+    }
+}
+
+/// Turns heading text into an anchor: lowercase alphanumerics, separated by single hyphens
+fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .split(|char: char| !char.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Converts markdown to HTML without relying on external programs.
+/// Headings without an explicit `{#id}` receive one derived from their text, numbered if it is already used by another heading.
+pub fn builtin(page: &PageResolver, source: &str) -> Result<Document> {
+    use pulldown_cmark::{CowStr, Event, Options, Parser, Tag, TagEnd};
+
+    let (front_matter, source) = front_matter(&page.path, source)?;
+    let options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_HEADING_ATTRIBUTES;
+
+    let mut events = Parser::new_ext(source, options).collect::<Vec<_>>();
+
+    // Explicit ids are taken first, wherever they appear, so generated ones never collide with them
+    let mut anchors = events.iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => Some(id.to_string()),
+            _ => None
+        })
+        .collect::<HashSet<_>>();
+
+    for i in 0..events.len() {
+        let Event::Start(Tag::Heading { id: None, .. }) = &events[i] else {
+            continue;
+        };
+
+        let text = events[i + 1..].iter()
+            .take_while(|event| !matches!(event, Event::End(TagEnd::Heading(_))))
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None
+            })
+            .collect::<String>();
+
+        let base = match slug(&text) {
+            slug if slug.is_empty() => "section".to_owned(),
+            slug => slug
+        };
+
+        let mut anchor = base.clone();
+        for n in 1.. {
+            if anchors.insert(anchor.clone()) {
+                break;
+            }
+
+            anchor = format!("{}-{}", base, n);
+        }
+
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+            *id = Some(CowStr::from(anchor));
+        }
+    }
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+
+    Ok(document(page, html, front_matter))
+}
+
+/// Converts markdown to HTML using the `pandoc` executable
+#[cfg(feature = "pandoc")]
+pub fn pandoc(page: &PageResolver, source: &str) -> Result<Document> {
+    let (front_matter, source) = front_matter(&page.path, source)?;
+
+    let mut pandoc = pandoc::new();
+    pandoc.set_input(pandoc::InputKind::Pipe(source.to_owned()))
+        .set_input_format(pandoc::InputFormat::Markdown, vec![])
+        .set_output_format(pandoc::OutputFormat::Html, vec![])
+        .set_output(pandoc::OutputKind::Pipe);
+
+    let html = match pandoc.execute() {
+        Ok(pandoc::PandocOutput::ToBuffer(html)) => html,
+        Ok(_) => unreachable!("Pandoc was asked to output to a buffer"),
+        Err(err) => return Err(Error::BuildError(BuildError::ConversionFailed {
+            path: page.path.clone(),
+            message: format!("{:?}", err),
+        })),
+    };
+
+    Ok(document(page, html, front_matter))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use crate::build::PageResolver;
+    use crate::config::LanguageConfig;
+    use crate::content_type::Handler;
+    use crate::content_type::markdown::{builtin, front_matter};
+
+    #[test]
+    fn test_front_matter() {
+        let (meta, body) = front_matter(Path::new("post.md"), "+++\ntitle = \"Hello\"\ntemplate = \"frame\"\n+++\n# Hello\n").unwrap();
+        assert_eq!(meta.get("title").and_then(|i| i.as_str()), Some("Hello"));
+        assert_eq!(body, "# Hello\n");
+
+        let (meta, body) = front_matter(Path::new("post.md"), "# No front matter\n+++\n").unwrap();
+        assert!(meta.is_empty());
+        assert_eq!(body, "# No front matter\n+++\n");
+
+        assert!(front_matter(Path::new("post.md"), "+++\ntitle = \"Unterminated\"\n").is_err());
+    }
+
+    fn render(source: &str) -> String {
+        let page = PageResolver {
+            path: PathBuf::from("index.en.md"),
+            name: "index".to_owned(),
+            title: None,
+            language: Arc::new(LanguageConfig::default()),
+            handler: Handler::Markdown,
+        };
+
+        let document = builtin(&page, source).unwrap();
+        match document.root.body.first() {
+            Some(crate::parse::Body::Element(escape)) => match escape.body.first() {
+                Some(crate::parse::Body::Literal(html)) => String::from_utf8(html.body.clone()).unwrap(),
+                _ => panic!("Expected a literal"),
+            },
+            _ => panic!("Expected an escape"),
+        }
+    }
+
+    #[test]
+    fn test_builtin() {
+        assert_eq!(render("# Hello *World*\n\n## Hello World\n\n## Custom {#custom}\n"),
+                   "<h1 id=\"hello-world\">Hello <em>World</em></h1>\n<h2 id=\"hello-world-1\">Hello World</h2>\n<h2 id=\"custom\">Custom</h2>\n");
+        assert_eq!(render("| a | b |\n|---|---|\n| 1 | 2 |\n"),
+                   "<table><thead><tr><th>a</th><th>b</th></tr></thead><tbody>\n<tr><td>1</td><td>2</td></tr>\n</tbody></table>\n");
+        assert!(render("Text[^1]\n\n[^1]: Note\n").contains("class=\"footnote-definition\""));
+
+        // An explicit id later in the page still takes precedence over a generated one
+        assert_eq!(render("## Intro\n\n## Overview {#intro}\n"),
+                   "<h2 id=\"intro-1\">Intro</h2>\n<h2 id=\"intro\">Overview</h2>\n");
+    }
+}
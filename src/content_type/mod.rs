@@ -0,0 +1,317 @@
+pub mod markdown;
+
+use std::sync::Arc;
+use rune::{Any, ContextError, Module};
+use rune::runtime::Value;
+use serde::Deserialize;
+use crate::{
+    build::PageResolver,
+    compile,
+    config::Config,
+    config::ContentType,
+    config::MarkdownRenderer,
+    error::*,
+    parse,
+    parse::Origin,
+    parse::ParsingContext,
+};
+
+/// How a page's source is turned into an element tree
+#[derive(Debug, Clone)]
+pub enum Handler {
+    /// Pages written in the template language itself
+    Native,
+    /// A `[[content-type]]` from the site config, whose script converts the source
+    Script(Arc<ContentType>),
+    /// Markdown, converted by the `pandoc` executable
+    #[cfg(feature = "pandoc")]
+    Pandoc,
+    /// Markdown, converted without relying on external programs
+    Markdown,
+}
+
+/// A page converted by its content type
+pub struct Document {
+    pub root: parse::Element,
+    /// Metadata found at the start of the source, exposed to templates as variables
+    pub front_matter: toml::Table,
+}
+
+impl From<parse::Element> for Document {
+    fn from(root: parse::Element) -> Self {
+        Self { root, front_matter: toml::Table::new() }
+    }
+}
+
+impl Handler {
+    pub fn extensions(&self) -> Vec<String> {
+        match self {
+            Self::Native => vec!["html".to_owned()],
+            Self::Script(content_type) => content_type.extensions.clone(),
+            #[cfg(feature = "pandoc")]
+            Self::Pandoc => vec!["md".to_owned()],
+            Self::Markdown => vec!["md".to_owned()],
+        }
+    }
+
+    /// Produces the element tree of the page
+    pub fn load(&self, page: &PageResolver, source: String) -> Result<Document> {
+        Ok(match self {
+            Self::Native => ParsingContext::new(source, page.path.clone())?.parse()?.into(),
+            Self::Script(content_type) => run(content_type, page, source)?.into(),
+            #[cfg(feature = "pandoc")]
+            Self::Pandoc => markdown::pandoc(page, &source)?,
+            Self::Markdown => markdown::builtin(page, &source)?,
+        })
+    }
+}
+
+/// Every content type of the site. Types defined in the site config take precedence over built-in ones handling the same extension.
+pub fn handlers(config: &Config) -> Result<Vec<Handler>> {
+    let markdown = match config.markdown {
+        #[cfg(feature = "pandoc")]
+        MarkdownRenderer::Auto | MarkdownRenderer::Pandoc => Handler::Pandoc,
+        #[cfg(not(feature = "pandoc"))]
+        MarkdownRenderer::Auto => Handler::Markdown,
+        #[cfg(not(feature = "pandoc"))]
+        MarkdownRenderer::Pandoc => return Err(Error::BuildError(BuildError::FeatureDisabled("pandoc".to_owned()))),
+        MarkdownRenderer::Builtin => Handler::Markdown,
+    };
+
+    Ok(config.content_types.iter()
+        .cloned()
+        .map(Handler::Script)
+        .chain([Handler::Native, markdown].into_iter()
+            .filter(|handler| handler.extensions().iter()
+                .all(|ext| !config.content_types.iter().any(|i| i.extensions.contains(ext)))))
+        .collect())
+}
+
+/// How the content type is referred to in errors
+fn describe(content_type: &ContentType) -> String {
+    format!("content type [{}]", content_type.extensions.join(", "))
+}
+
+/// Calls the `content_type(origin, page)` function of the content type's script, converting its result into an element tree
+fn run(content_type: &ContentType, page: &PageResolver, source: String) -> Result<parse::Element> {
+    let origin = Origin {
+        source: page.path.clone(),
+        offset: 0,
+        depth: 0,
+        token_length: source.len(),
+    };
+
+    let error = |message: String| Error::RuneError(RuneError {
+        origin: origin.clone(),
+        message: format!("{}: {}", describe(content_type), message),
+    });
+
+    let unit = compile::compile(&content_type.script.source, &origin)
+        .map_err(|err| match err {
+            Error::RuneError(err) => error(err.message),
+            err => err
+        })?;
+
+    let mut info = page.info();
+    info.insert("source".to_owned(), toml::Value::String(source));
+
+    let page = Value::deserialize(toml::Value::Table(info)).map_err(|err| error(err.to_string()))?;
+    let value = compile::vm(unit).call(["content_type"], (origin.clone(), page))
+        .map_err(|err| error(err.to_string()))?;
+
+    // Handlers may return the element directly or wrapped in a `Result`
+    let value = match value {
+        Value::Result(result) => match result.take().map_err(|err| error(err.to_string()))? {
+            Ok(value) => value,
+            Err(Value::String(message)) => return Err(error(message.take().map_err(|err| error(err.to_string()))?.to_string())),
+            Err(value) => return Err(error(format!("{:?}", value))),
+        },
+        value => value
+    };
+
+    let mut body = to_body(value, &origin).map_err(error)?;
+
+    match body.pop() {
+        Some(parse::Body::Element(element)) if body.is_empty() => Ok(element),
+        _ => Err(error("expected the handler to return a single element".to_owned()))
+    }
+}
+
+/// The body of the page a content type produced, along with the origin every node it contains is attributed to
+#[derive(Debug, Any)]
+#[rune(item = ::jcake_ssg::elements, constructor)]
+pub struct PageElement {
+    #[rune(get, set)]
+    pub body: Value,
+    #[rune(get, set)]
+    pub origin: Origin,
+}
+
+/// Text, which is escaped when rendered
+#[derive(Debug, Clone, Any)]
+#[rune(item = ::jcake_ssg::elements)]
+pub struct TextElement {
+    #[rune(get)]
+    pub text: String,
+}
+
+impl TextElement {
+    #[rune::function(path = Self::new)]
+    pub fn new(text: String) -> Self {
+        Self { text }
+    }
+}
+
+/// Any other element. Elements named after built-in tags, such as `page` or `include`, behave as if they were written in markup.
+#[derive(Debug, Any)]
+#[rune(item = ::jcake_ssg::elements)]
+pub struct Element {
+    #[rune(get)]
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub body: Vec<Value>,
+}
+
+impl Element {
+    #[rune::function(path = Self::new)]
+    pub fn new(name: String) -> Self {
+        Self { name, attributes: vec![], body: vec![] }
+    }
+
+    #[rune::function(instance)]
+    pub fn set_attribute(&mut self, name: String, value: String) {
+        self.attributes.retain(|(i, _)| i != &name);
+        self.attributes.push((name, value));
+    }
+
+    #[rune::function(instance)]
+    pub fn push(&mut self, child: Value) {
+        self.body.push(child);
+    }
+}
+
+pub fn module() -> core::result::Result<Module, ContextError> {
+    let mut module = Module::with_crate_item("jcake_ssg", ["elements"])?;
+
+    module.ty::<PageElement>()?;
+    module.ty::<TextElement>()?;
+    module.function_meta(TextElement::new)?;
+    module.ty::<Element>()?;
+    module.function_meta(Element::new)?;
+    module.function_meta(Element::set_attribute)?;
+    module.function_meta(Element::push)?;
+
+    Ok(module)
+}
+
+fn literal(text: String, origin: &Origin) -> parse::Body {
+    parse::Body::Literal(parse::Literal {
+        body: text.into_bytes(),
+        origin: origin.clone(),
+        is_byte_string: false,
+    })
+}
+
+fn element(name: &str, attributes: Vec<(String, String)>, body: Vec<parse::Body>, origin: &Origin) -> parse::Body {
+    parse::Body::Element(parse::Element {
+        name: name.to_lowercase(),
+        attributes: attributes.into_iter()
+            .map(|(name, value)| parse::Attribute {
+                name,
+                value,
+                is_expression: false,
+                is_boolean: false,
+                origin: origin.clone(),
+            })
+            .collect(),
+        body,
+        origin: origin.clone(),
+    })
+}
+
+/// Converts a value returned by a handler into nodes. Vectors are flattened, strings become text and `Markup` is emitted as-is.
+fn to_body(value: Value, origin: &Origin) -> core::result::Result<Vec<parse::Body>, String> {
+    Ok(match value {
+        Value::EmptyTuple => vec![],
+        Value::String(_) => vec![literal(rune::from_value::<String>(value).map_err(|err| err.to_string())?, origin)],
+        Value::Option(_) => match rune::from_value::<Option<Value>>(value).map_err(|err| err.to_string())? {
+            Some(value) => to_body(value, origin)?,
+            None => vec![],
+        },
+        Value::Vec(_) => {
+            let mut body = vec![];
+            for value in rune::from_value::<Vec<Value>>(value).map_err(|err| err.to_string())? {
+                body.extend(to_body(value, origin)?);
+            }
+            body
+        },
+        value => {
+            if let Ok(page) = rune::from_value::<PageElement>(value.clone()) {
+                vec![element("page", vec![], to_body(page.body, &page.origin)?, &page.origin)]
+            } else if let Ok(text) = rune::from_value::<TextElement>(value.clone()) {
+                vec![literal(text.text, origin)]
+            } else if let Ok(compile::Markup(markup)) = rune::from_value::<compile::Markup>(value.clone()) {
+                vec![element("escape", vec![("mode".to_owned(), "raw".to_owned())], vec![literal(markup, origin)], origin)]
+            } else if let Ok(el) = rune::from_value::<Element>(value.clone()) {
+                let mut body = vec![];
+                for value in el.body.into_iter() {
+                    body.extend(to_body(value, origin)?);
+                }
+                vec![element(&el.name, el.attributes, body, origin)]
+            } else {
+                return Err(format!("cannot convert {:?} into an element", value));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use crate::build::PageResolver;
+    use crate::config::{ContentType, LanguageConfig, Script};
+    use crate::content_type::Handler;
+    use crate::error::*;
+
+    fn load(handler: &str, source: &str) -> Result<String> {
+        let content_type = Arc::new(ContentType {
+            extensions: vec!["txt".to_owned()],
+            script: Script {
+                source: handler.to_owned(),
+                ..Script::default()
+            },
+        });
+
+        let page = PageResolver {
+            path: PathBuf::from("index.en.txt"),
+            name: "index".to_owned(),
+            title: None,
+            language: Arc::new(LanguageConfig::default()),
+            handler: Handler::Script(content_type.clone()),
+        };
+
+        Ok(format!("{:?}", Handler::Script(content_type).load(&page, source.to_owned())?.root))
+    }
+
+    #[test]
+    fn test_content_type() {
+        let handler = r#"
+            use jcake_ssg::elements::{Element, PageElement, TextElement};
+
+            pub fn content_type(origin, page) {
+                let heading = Element::new("h1");
+                heading.push(TextElement::new(page.name));
+
+                Ok(PageElement {
+                    body: [heading, page.source],
+                    origin
+                })
+            }
+        "#;
+
+        assert_eq!(load(handler, "Hello").unwrap(), "<page>\n<h1>\n\"index\"\n</h1>\n\"Hello\"\n</page>");
+        assert!(matches!(load("pub fn content_type(origin, page) { Err(\"Nope\") }", ""), Err(Error::RuneError(err)) if err.message == "content type [txt]: Nope"));
+        assert!(matches!(load("pub fn content_type(origin, page) { 1 }", ""), Err(Error::RuneError(_))));
+    }
+}
@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use crate::parse::Origin;
+
 macro_rules! multi_error {
     ($name:ident($($manual:ident),*); $($err:ident = $obj:ty);*) => {
         /// Each line represents a possible error type, acting as a union between all error types below.
@@ -9,7 +12,14 @@ macro_rules! multi_error {
             $($manual),*
         }
 
-        impl std::fmt::Display for Error { fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { std::fmt::Debug::fmt(self, f) } }
+        impl std::fmt::Display for Error {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Self::$err(err) => std::fmt::Display::fmt(err, f),)*
+                    $(Self::$manual => f.write_str(stringify!($manual)),)*
+                }
+            }
+        }
         impl std::error::Error for Error {}
 
         $(impl From<$obj> for $name { fn from(value: $obj) -> Self { Self::$err(value) } })*
@@ -24,27 +34,261 @@ multi_error! { Error();
     WalkError = globwalk::WalkError;
     JoinError = tokio::task::JoinError;
     RegexError = regex::Error;
+    NotifyError = notify::Error;
     BuildError = BuildError;
-    RuneError = rune::runtime::VmError
+    RuneError = RuneError
+}
+
+impl Error {
+    /// The message, origin and hint of an error found at a particular place in a source
+    pub fn located(&self) -> Option<(String, &Origin, Option<String>)> {
+        match self {
+            Self::BuildError(err) => err.origin().map(|origin| (err.message(), origin, err.hint())),
+            Self::RuneError(err) => Some((err.message.clone(), &err.origin, None)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum BuildError {
-    MatchedDirectory(PathBuf),
-    InvalidSyntax(String),
-    NoTagName,
+    /// A raw string which is never terminated
+    InvalidSyntax(Origin),
+    /// A `<` which starts a tag the tokenizer can't make sense of, such as `</>` or an attribute without a name
+    InvalidTag(Origin),
+
+    NoClosingTag { tag: String, origin: Origin },
+    /// A closing tag which doesn't close any open element
+    UnexpectedClosingTag { tag: String, origin: Origin },
+    BadSelectorList(Origin),
+    BracketMismatch(Origin),
+
+    InvalidCharacterCode { code: String, origin: Origin },
+    UnexpectedEOF(Origin),
+
+    MissingAttribute { tag: String, attribute: String, origin: Origin },
+    InvalidAttribute { tag: String, attribute: String, value: String, origin: Origin },
+    DuplicateDefinition { name: String, origin: Origin },
+    NoSuchTemplate { name: String, origin: Origin },
+    /// A file includes itself, either directly or transitively. Holds every file in the chain, ending with the repeated one.
+    IncludeCycle(Vec<PathBuf>),
+    /// A component is used within its own body, either directly or through other components. Holds every component in the chain, ending with the repeated one.
+    ComponentCycle { chain: Vec<String>, origin: Origin },
+    /// An `else-if` or `else` branch which doesn't follow a `<condition>`
+    DanglingBranch { branch: String, origin: Origin },
+    /// A built-in content type failed to convert a file
+    ConversionFailed { path: PathBuf, message: String },
+    /// A language was requested which the config doesn't define
+    UnknownLanguage(String),
+    /// Creating a new site would overwrite an existing file
+    AlreadyExists(PathBuf),
+    /// Removing files from the build directory could remove the site's own sources, as the build directory contains the given path
+    UnsafeBuildDirectory { build: PathBuf, contains: PathBuf },
+    /// The config requires a cargo feature this build was compiled without
+    FeatureDisabled(String),
+    /// A config section sets both `handler` and `handler_path`
+    AmbiguousScript(String),
+    /// A config section which requires a script sets neither `handler` nor `handler_path`
+    MissingScript(String),
+}
+
+impl BuildError {
+    /// Where in the source the error was found, if it was found in a source at all
+    pub fn origin(&self) -> Option<&Origin> {
+        match self {
+            Self::InvalidSyntax(origin)
+            | Self::InvalidTag(origin)
+            | Self::NoClosingTag { origin, .. }
+            | Self::UnexpectedClosingTag { origin, .. }
+            | Self::BadSelectorList(origin)
+            | Self::BracketMismatch(origin)
+            | Self::InvalidCharacterCode { origin, .. }
+            | Self::UnexpectedEOF(origin)
+            | Self::MissingAttribute { origin, .. }
+            | Self::InvalidAttribute { origin, .. }
+            | Self::DuplicateDefinition { origin, .. }
+            | Self::NoSuchTemplate { origin, .. }
+            | Self::ComponentCycle { origin, .. }
+            | Self::DanglingBranch { origin, .. } => Some(origin),
+            _ => None,
+        }
+    }
+
+    /// A suggestion on how to fix the error
+    pub fn hint(&self) -> Option<String> {
+        Some(match self {
+            Self::InvalidSyntax(_) => "raw strings end with their opening quote followed by as many `#`s as they started with".to_owned(),
+            Self::InvalidTag(_) => "write a literal `<` in text as `&lt;`".to_owned(),
+            Self::NoClosingTag { tag, .. } => format!("close the element with `</{tag}>`, or write it as `<{tag} />` if it has no body"),
+            Self::UnexpectedClosingTag { tag, .. } => format!("remove the `</{tag}>`, or add the `<{tag}>` it is meant to close"),
+            Self::BadSelectorList(_) => "the shorthand after a tag name takes at most one `#id` and any number of `.class`es".to_owned(),
+            Self::BracketMismatch(_) => "every `{` must be matched by a `}`".to_owned(),
+            Self::InvalidCharacterCode { .. } => "unicode escapes take the hexadecimal code of a character, as in `\\u{1F600}`".to_owned(),
+            Self::MissingAttribute { tag, attribute, .. } => format!("add `{attribute}=\"...\"` to the `<{tag}>`"),
+            Self::NoSuchTemplate { .. } => "templates are defined with `<template name=\"...\" bind=\"...\">` in one of the template directories".to_owned(),
+            Self::DuplicateDefinition { .. } => "every template and component must have a unique name".to_owned(),
+            Self::ComponentCycle { .. } => "a component can't be used within its own body, directly or through other components".to_owned(),
+            Self::DanglingBranch { .. } => "an `else-if` or `else` branch must directly follow a `<condition if>` or another `else-if`".to_owned(),
+            _ => return None,
+        })
+    }
+}
+
+impl BuildError {
+    /// What went wrong, without where or how to fix it
+    pub fn message(&self) -> String {
+        match self {
+            Self::InvalidSyntax(_) => "unterminated raw string".to_owned(),
+            Self::InvalidTag(_) => "malformed tag".to_owned(),
+            Self::NoClosingTag { tag, .. } => format!("`<{}>` is never closed", tag),
+            Self::UnexpectedClosingTag { tag, .. } => format!("`</{}>` doesn't close any open element", tag),
+            Self::BadSelectorList(_) => "invalid selector shorthand".to_owned(),
+            Self::BracketMismatch(_) => "unmatched `{`".to_owned(),
+            Self::InvalidCharacterCode { code, .. } => format!("`{}` is not a valid character code", code),
+            Self::UnexpectedEOF(_) => "unexpected end of file".to_owned(),
+            Self::MissingAttribute { tag, attribute, .. } => format!("`<{}>` requires a `{}` attribute", tag, attribute),
+            Self::InvalidAttribute { tag, attribute, value, .. } => format!("`{}` is not a valid value for the `{}` attribute of `<{}>`", value, attribute, tag),
+            Self::DuplicateDefinition { name, .. } => format!("`{}` is defined more than once", name),
+            Self::NoSuchTemplate { name, .. } => format!("no template named `{}`", name),
+            Self::IncludeCycle(chain) => format!("include cycle: {}", chain.iter().map(|i| format!("{:?}", i)).collect::<Vec<_>>().join(" -> ")),
+            Self::ComponentCycle { chain, .. } => format!("component cycle: {}", chain.iter().map(|i| format!("<{}>", i)).collect::<Vec<_>>().join(" -> ")),
+            Self::DanglingBranch { branch, .. } => format!("`{}` branch without a preceding `<condition>`", branch),
+            Self::ConversionFailed { path, message } => format!("failed to convert {:?}: {}", path, message),
+            Self::UnknownLanguage(language) => format!("the config doesn't define the language `{}`", language),
+            Self::AlreadyExists(path) => format!("{:?} already exists", path),
+            Self::UnsafeBuildDirectory { build, contains } => format!("nothing is removed from the build directory {:?}, as it contains {:?}", build, contains),
+            Self::FeatureDisabled(feature) => format!("the config requires the `{}` feature, which this build was compiled without", feature),
+            Self::AmbiguousScript(section) => format!("{} sets both `handler` and `handler_path`", section),
+            Self::MissingScript(section) => format!("{} sets neither `handler` nor `handler_path`", section),
+        }
+    }
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.origin() {
+            Some(origin) => Diagnostic { message: self.message(), origin, hint: self.hint() }.fmt(f),
+            None => f.write_str(&self.message()),
+        }
+    }
+}
+
+/// A script failed to compile or run. `origin` points at the source of the script, not the generated code the script was embedded into.
+#[derive(Debug)]
+pub struct RuneError {
+    pub origin: Origin,
+    pub message: String,
+}
+
+impl Display for RuneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Diagnostic { message: self.message.clone(), origin: &self.origin, hint: None }.fmt(f)
+    }
+}
+
+/// A message about a span of source, printed as compilers do:
+///
+/// ```text
+/// `<div>` is never closed
+///  --> www/about.en.html:3:5
+///    |
+///  3 |     <div class="intro">
+///    |     ^^^^^^^^^^^^^^^^^^^
+///    = hint: close the element with `</div>`, or write it as `<div />` if it has no body
+/// ```
+///
+/// The source is read back from the origin's file. If it can't be, only the path is printed.
+pub struct Diagnostic<'a> {
+    pub message: String,
+    pub origin: &'a Origin,
+    pub hint: Option<String>,
+}
+
+/// Paths within the working directory are shown relative to it
+pub fn display_path(path: &Path) -> String {
+    std::env::current_dir().ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.message)?;
+
+        let path = display_path(&self.origin.source);
+        let source = std::fs::read_to_string(&self.origin.source).ok();
+
+        match source.as_deref().and_then(|source| self.origin.position(source).map(|position| (source, position))) {
+            Some((source, (line, column))) => {
+                let start = source[..self.origin.offset].rfind('\n').map_or(0, |i| i + 1);
+                let text = source[start..].lines().next().unwrap_or("");
+                let before = &source[start..self.origin.offset];
+
+                // The span is cut off at the end of its first line
+                let span = text[before.len()..].chars()
+                    .take(self.origin.len())
+                    .count()
+                    .max(1);
+
+                // Tabs are kept so the caret lines up however wide they are shown
+                let indent = before.chars()
+                    .map(|char| if char == '\t' { '\t' } else { ' ' })
+                    .collect::<String>();
 
-    NoClosingTag,
-    NoSelectorList,
-    BadSelectorList,
-    NotATag,
-    NotAnExpression,
-    BracketMismatch,
-    NotALiteral,
-    ByteStringNotSupported,
+                let gutter = " ".repeat(line.to_string().len());
+                writeln!(f, "{}--> {}:{}:{}", gutter, path, line, column)?;
+                writeln!(f, "{} |", gutter)?;
+                writeln!(f, "{} | {}", line, text)?;
+                write!(f, "{} | {}{}", gutter, indent, "^".repeat(span))?;
 
-    InvalidCharacterCode(String),
-    UnexpectedEOF,
+                if let Some(hint) = &self.hint {
+                    write!(f, "\n{} = hint: {}", gutter, hint)?;
+                }
+            },
+            None => {
+                write!(f, " --> {}", path)?;
+
+                if let Some(hint) = &self.hint {
+                    write!(f, "\n = hint: {}", hint)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
-pub type Result<T> = core::result::Result<T, Error>;
\ No newline at end of file
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[cfg(test)]
+mod test {
+    use crate::error::*;
+    use crate::parse::ParsingContext;
+
+    #[test]
+    fn test_diagnostic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.html");
+        let source = "<page>\n\t<div.intro>\n\t\t<p>\"Hi\"</p>\n</page>\n";
+        std::fs::write(&path, source).unwrap();
+
+        let err = ParsingContext::new(source, path.clone()).unwrap().parse().unwrap_err();
+        assert!(matches!(&err, Error::BuildError(BuildError::NoClosingTag { tag, .. }) if tag == "div"));
+        assert_eq!(err.to_string(), format!("`<div>` is never closed\n --> {}:2:2\n  |\n2 | \t<div.intro>\n  | \t^^^^^^^^^^^\n  = hint: close the element with `</div>`, or write it as `<div />` if it has no body", path.display()));
+
+        // Without the source, only the path is shown
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.to_string(), format!("`<div>` is never closed\n --> {}\n = hint: close the element with `</div>`, or write it as `<div />` if it has no body", path.display()));
+    }
+
+    #[test]
+    fn test_attribute_origin() {
+        let root = ParsingContext::new(r#"<a  href="/" hidden>"x"</a>"#, "test.html").unwrap().parse().unwrap();
+        let crate::parse::Body::Element(a) = &root.body[0] else { panic!("Expected an element") };
+
+        let origins = a.attributes.iter().map(|attr| (attr.name.as_str(), attr.origin.offset)).collect::<Vec<_>>();
+        assert_eq!(origins, [("href", 4), ("hidden", 13)]);
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use regex::Regex;
+use crate::{
+    build::ARGS,
+    build::PageResolver,
+    build::config,
+    build::languages,
+    config::LanguageConfig,
+    error::*,
+    parse,
+    parse::Origin,
+    parse::ParsingContext,
+    report,
+    report::Problem,
+    report::Severity,
+    template::resolve_path,
+};
+
+/// The attributes each built-in element understands. Any other built-in passes its attributes on as variables, so accepts any.
+/// `slot` is accepted everywhere, as any element may fill a component's slot.
+const ATTRIBUTES: [(&str, &[&str]); 6] = [
+    ("block", &["name"]),
+    ("template", &["name", "bind"]),
+    ("escape", &["mode"]),
+    ("condition", &["if", "else-if", "else"]),
+    ("slot", &["name"]),
+    ("fragment", &["origin"]),
+];
+
+fn problem(severity: Severity, code: &'static str, file: &Path, origin: Option<&Origin>, message: String, hint: Option<&str>) -> Problem {
+    Problem {
+        severity,
+        code: Some(code),
+        file: file.to_path_buf(),
+        message,
+        origin: origin.cloned(),
+        hint: hint.map(str::to_owned),
+    }
+}
+
+/// The value of a condition which is the same every time it is evaluated, if it is
+fn constant(condition: &str) -> Option<bool> {
+    match condition.trim() {
+        "true" => Some(true),
+        "false" | "()" | "\"\"" | "''" | "None" => Some(false),
+        number => number.parse::<f64>().ok().map(|number| number != 0.0),
+    }
+}
+
+/// Walks a single tree, collecting every problem found in it
+struct Linter<'a> {
+    file: &'a Path,
+    /// Static ids seen so far which could end up in the same document. Ids given as expressions can't be known without rendering.
+    ids: HashSet<String>,
+    problems: Vec<Problem>,
+}
+
+impl Linter<'_> {
+    fn element(&mut self, element: &parse::Element) {
+        self.attributes(element);
+        self.ids(element);
+        self.conditions(&element.body);
+
+        // A template's body is rendered into other pages, so its ids can only clash with each other
+        let outer = (element.name == "template").then(|| std::mem::take(&mut self.ids));
+
+        // Only one branch of a chain is rendered, so each starts from the ids seen before the chain, which ends up with the ids of every branch
+        let mut chain = None;
+
+        for child in element.body.iter() {
+            let parse::Body::Element(child) = child else {
+                self.end_chain(&mut chain);
+                continue;
+            };
+
+            if child.name != "condition" || child.attributes.iter().any(|i| i.name == "if") {
+                self.end_chain(&mut chain);
+            }
+
+            if child.name != "condition" {
+                self.element(child);
+                continue;
+            }
+
+            let (before, _) = chain.get_or_insert_with(|| (self.ids.clone(), HashSet::new()));
+            self.ids = before.clone();
+            self.element(child);
+
+            if let Some((_, after)) = &mut chain {
+                after.extend(std::mem::take(&mut self.ids));
+            }
+        }
+
+        self.end_chain(&mut chain);
+
+        if let Some(outer) = outer {
+            self.ids = outer;
+        }
+    }
+
+    /// Continues after a condition chain with the ids of all of its branches
+    fn end_chain(&mut self, chain: &mut Option<(HashSet<String>, HashSet<String>)>) {
+        if let Some((_, after)) = chain.take() {
+            self.ids = after;
+        }
+    }
+
+    fn attributes(&mut self, element: &parse::Element) {
+        if let Some((_, known)) = ATTRIBUTES.iter().find(|(name, _)| *name == element.name) {
+            for attribute in element.attributes.iter().filter(|i| i.name != "slot" && !known.contains(&i.name.as_str())) {
+                self.problems.push(problem(Severity::Warning, "unknown-attribute", self.file, Some(&attribute.origin),
+                    format!("`<{}>` has no `{}` attribute", element.name, attribute.name),
+                    Some(&format!("`<{}>` understands {}", element.name, known.iter().map(|i| format!("`{}`", i)).collect::<Vec<_>>().join(", ")))));
+            }
+        }
+
+        if element.name == "template" {
+            for attribute in ["name", "bind"].into_iter().filter(|attribute| !element.attributes.iter().any(|i| i.name == *attribute)) {
+                self.problems.push(problem(Severity::Error, "missing-attribute", self.file, Some(&element.origin),
+                    format!("`<template>` requires a `{}` attribute", attribute),
+                    Some("templates are defined with `<template name=\"...\" bind=\"...\">`")));
+            }
+        }
+    }
+
+    fn ids(&mut self, element: &parse::Element) {
+        for id in element.attributes.iter().filter(|i| i.name == "id" && !i.is_expression) {
+            if !self.ids.insert(id.value.clone()) {
+                self.problems.push(problem(Severity::Warning, "duplicate-id", self.file, Some(&id.origin),
+                    format!("duplicate id `{}`", id.value),
+                    Some("ids must be unique within a file, including those given with the `#id` shorthand")));
+            }
+        }
+    }
+
+    /// Finds branches of condition chains which can never be taken.
+    /// A chain is a run of consecutive `<condition>`s, as rendered by `render_body`.
+    fn conditions(&mut self, body: &[parse::Body]) {
+        // Whether an earlier branch of the current chain is always taken
+        let mut decided = false;
+
+        for child in body.iter() {
+            let parse::Body::Element(condition) = child else {
+                decided = false;
+                continue;
+            };
+
+            if condition.name != "condition" {
+                decided = false;
+                continue;
+            }
+
+            let attribute = |name: &str| condition.attributes.iter().find(|i| i.name == name);
+            let test = attribute("if").or_else(|| attribute("else-if"));
+
+            if attribute("if").is_some() {
+                decided = false;
+            } else if decided {
+                self.problems.push(problem(Severity::Warning, "unreachable-branch", self.file, Some(&condition.origin),
+                    "this branch is never taken".to_owned(),
+                    Some("an earlier branch of the chain is always taken")));
+                continue;
+            }
+
+            match test.map(|test| (test, constant(&test.value))) {
+                Some((test, Some(false))) => self.problems.push(problem(Severity::Warning, "unreachable-branch", self.file, Some(&test.origin),
+                    "this branch is never taken".to_owned(),
+                    Some("its condition is always false"))),
+                Some((_, Some(true))) => decided = true,
+                Some((_, None)) => {},
+                // An `else` ends the chain
+                None => decided = true,
+            }
+        }
+    }
+}
+
+/// Checks a tree for problems which don't need it to be rendered to be found
+pub fn lint(root: &parse::Element, file: &Path) -> Vec<Problem> {
+    let mut linter = Linter { file, ids: HashSet::new(), problems: Vec::new() };
+    linter.element(root);
+
+    linter.problems
+}
+
+/// Pages which exist in some of the languages being built, but not in others
+pub fn missing_languages(pages: &[PageResolver], languages: &[Arc<LanguageConfig>]) -> Vec<Problem> {
+    let mut names = BTreeMap::<&str, Vec<&PageResolver>>::new();
+    for page in pages {
+        names.entry(&page.name).or_default().push(page);
+    }
+
+    names.into_iter()
+        .filter_map(|(name, pages)| {
+            let missing = languages.iter()
+                .filter(|language| !pages.iter().any(|page| page.language.name == language.name))
+                .map(|language| format!("`{}`", language.name))
+                .collect::<Vec<_>>();
+
+            if missing.is_empty() {
+                return None;
+            }
+
+            let file = pages.iter().map(|page| &page.path).min()?;
+            Some(problem(Severity::Warning, "missing-language", file, None,
+                format!("page `{}` has no version in {}", name, missing.join(", ")),
+                None))
+        })
+        .collect()
+}
+
+/// Every translation key the tree's expressions use, as matched by `pattern`'s first group, as in `page.translation.title`
+fn translation_keys<'a>(element: &'a parse::Element, pattern: &Regex, keys: &mut Vec<(String, &'a Origin)>) {
+    let find = |expression: &str, origin: &'a Origin| pattern.captures_iter(expression)
+        .map(|captures| (captures[1].to_owned(), origin))
+        .collect::<Vec<_>>();
+
+    for attribute in element.attributes.iter().filter(|i| i.is_expression) {
+        keys.extend(find(&attribute.value, &attribute.origin));
+    }
+
+    for child in element.body.iter() {
+        match child {
+            parse::Body::Expression(expression) => keys.extend(find(&expression.body, &expression.origin)),
+            parse::Body::Element(child) => translation_keys(child, pattern, keys),
+            parse::Body::Literal(_) => {},
+        }
+    }
+}
+
+fn find_page(element: &parse::Element) -> Option<&parse::Element> {
+    if element.name == "page" {
+        return Some(element);
+    }
+
+    element.body.iter().find_map(|child| match child {
+        parse::Body::Element(child) => find_page(child),
+        _ => None,
+    })
+}
+
+/// Checks that every translation key the page uses is defined in its language's table of the page's `translation` file
+async fn translations(page: &PageResolver, root: &parse::Element) -> Result<Vec<Problem>> {
+    let Some(element) = find_page(root) else {
+        return Ok(vec![]);
+    };
+
+    let attribute = |name: &str| element.attributes.iter().find(|i| i.name == name && !i.is_expression);
+    let Some(translation) = attribute("translation") else {
+        return Ok(vec![]);
+    };
+
+    let bound = attribute("translation-bound").map_or("translation", |i| i.value.as_str());
+    let path = resolve_path(&translation.value, page.path.clone());
+
+    let table = match tokio::fs::read_to_string(&path).await.map_err(Error::from).and_then(|source| Ok(toml::de::from_str::<toml::Table>(&source)?)) {
+        Ok(table) => table,
+        Err(err) => return Ok(vec![problem(Severity::Error, "undefined-translation", &page.path, Some(&translation.origin),
+            format!("the translation file {:?} can't be read: {}", path, err),
+            None)]),
+    };
+
+    let Some(table) = table.get(&page.language.name).and_then(|i| i.as_table()) else {
+        return Ok(vec![problem(Severity::Warning, "undefined-translation", &page.path, Some(&translation.origin),
+            format!("the translation file has no `[{}]` table", page.language.name),
+            None)]);
+    };
+
+    let pattern = Regex::new(&format!(r"\bpage\.{}\.([a-zA-Z_][a-zA-Z0-9_-]*)", regex::escape(bound)))?;
+    let mut keys = Vec::new();
+    translation_keys(root, &pattern, &mut keys);
+
+    Ok(keys.into_iter()
+        .filter(|(key, _)| !table.contains_key(key))
+        .map(|(key, origin)| problem(Severity::Warning, "undefined-translation", &page.path, Some(origin),
+            format!("`{}` is not translated into `{}`", key, page.language.name),
+            Some(&format!("add `{} = \"...\"` to the `[{}]` table of {:?}", key, page.language.name, path))))
+        .collect())
+}
+
+/// Lints the given pages and every template definition of the site, reporting what is found.
+/// Files which can't be read or fail to parse are skipped, as rendering them reports why.
+pub async fn lint_all(pages: &[PageResolver]) -> Result<()> {
+    let args = ARGS.get().expect("Args not set").clone();
+    let config = config();
+
+    missing_languages(pages, &languages(&config, &args)?).into_iter().for_each(report::report);
+
+    for page in pages.iter() {
+        let Ok(source) = tokio::fs::read_to_string(&page.path).await else {
+            continue;
+        };
+
+        let Ok(document) = page.handler.load(page, source) else {
+            continue;
+        };
+
+        lint(&document.root, &page.path).into_iter().for_each(report::report);
+        translations(page, &document.root).await?.into_iter().for_each(report::report);
+    }
+
+    for dir in config.templates.iter().map(|dir| args.root.join(dir)).filter(|dir| dir.exists()) {
+        for file in globwalk::GlobWalkerBuilder::from_patterns(dir.canonicalize()?, &["**/*.html"]).build()? {
+            let file: PathBuf = file?.into_path();
+            let Ok(source) = tokio::fs::read_to_string(&file).await else {
+                continue;
+            };
+
+            let Ok(root) = ParsingContext::new(source, file.clone())?.parse() else {
+                continue;
+            };
+
+            lint(&root, &file).into_iter().for_each(report::report);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use crate::build::PageResolver;
+    use crate::config::LanguageConfig;
+    use crate::content_type::Handler;
+    use crate::lint::{lint, missing_languages};
+    use crate::parse::ParsingContext;
+
+    fn codes(source: &str) -> Vec<(&'static str, String)> {
+        let root = ParsingContext::new(source.to_owned(), "test.html").unwrap().parse().unwrap();
+        lint(&root, Path::new("test.html")).into_iter()
+            .map(|problem| (problem.code.unwrap(), problem.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(codes(r#"<escape mode="raw" lang="en">"a"</escape><block name="b" slot="c" />"#), [("unknown-attribute", "`<escape>` has no `lang` attribute".to_owned())]);
+        assert_eq!(codes(r#"<template name="frame">"a"</template>"#), [("missing-attribute", "`<template>` requires a `bind` attribute".to_owned())]);
+        assert_eq!(codes(r#"<div#main><p#intro /><p.a#main /></div>"#), [("duplicate-id", "duplicate id `main`".to_owned())]);
+        assert!(codes(r#"<condition if={page.title}>"a"</condition><condition else>"b"</condition>"#).is_empty());
+
+        // Only one branch of a chain is ever rendered, but whichever it is still shares the ids around the chain
+        assert!(codes(r#"<condition if={x}><p#a /></condition><condition else-if={y}><p#a /></condition><condition else><p#a /></condition>"#).is_empty());
+        assert_eq!(codes(r#"<p#a /><condition if={x}><p#a /></condition><condition else><p#b /></condition><p#b />"#).len(), 2);
+        assert_eq!(codes(r#"<condition if={x}><p#a /></condition><condition if={y}><p#a /></condition>"#).len(), 1);
+
+        // Each template is rendered on its own
+        assert!(codes(r#"<p#a /><template name="x" bind="y"><p#a /></template><template name="z" bind="y"><p#a /></template>"#).is_empty());
+        assert_eq!(codes(r#"<template name="x" bind="y"><p#a /><p#a /></template>"#).len(), 1);
+
+        let unreachable = codes(r#"<condition if="false">"a"</condition><condition if="true">"b"</condition><condition else-if={x}>"c"</condition><condition else>"d"</condition><p /><condition if={x}>"e"</condition>"#);
+        assert_eq!(unreachable.iter().map(|(code, _)| *code).collect::<Vec<_>>(), ["unreachable-branch"; 3]);
+    }
+
+    #[test]
+    fn test_missing_languages() {
+        let language = |name: &str| Arc::new(LanguageConfig { name: name.to_owned(), ..LanguageConfig::default() });
+        let page = |name: &str, lang: &str| PageResolver {
+            path: PathBuf::from(format!("www/{}.{}.html", name, lang)),
+            name: name.to_owned(),
+            title: None,
+            language: language(lang),
+            handler: Handler::Native,
+        };
+
+        let problems = missing_languages(&[page("about", "en"), page("about", "de"), page("blog", "de")], &[language("en"), language("de"), language("fr")]);
+        assert_eq!(problems.iter().map(|problem| problem.message.as_str()).collect::<Vec<_>>(), ["page `about` has no version in `fr`", "page `blog` has no version in `en`, `fr`"]);
+        assert_eq!(problems[1].file, PathBuf::from("www/blog.de.html"));
+    }
+}
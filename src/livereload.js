@@ -0,0 +1,14 @@
+(() => {
+    const events = new EventSource("/__livereload");
+
+    events.addEventListener("reload", () => location.reload());
+
+    // Stylesheets are swapped in place, preserving the page's state
+    events.addEventListener("styles", () => {
+        for (const link of document.querySelectorAll('link[rel="stylesheet"]')) {
+            const url = new URL(link.href);
+            url.searchParams.set("livereload", Date.now().toString());
+            link.href = url.href;
+        }
+    });
+})();
@@ -3,48 +3,91 @@ mod config;
 mod build;
 mod parse;
 mod compile;
+mod content_type;
 mod template;
+mod watch;
+mod assets;
+mod report;
+mod lint;
+mod scaffold;
+#[cfg(feature = "serve")]
+mod serve;
 
 use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::{Arc, OnceLock};
 use clap::Parser;
-use tokio::task::JoinSet;
 
 pub use error::*;
 use crate::{
-    build::build,
+    build::build_all,
+    build::check_all,
+    build::clean,
+    build::load,
     build::ARGS,
-    config::Config,
     config::Args,
-    build::CONFIG
+    config::Command,
+    report::Format,
 };
-use crate::build::list_pages;
 
 pub static SITE_ROOT: OnceLock<Arc<PathBuf>> = OnceLock::new();
 
 #[tokio::main]
-pub async fn main() -> Result<()> {
+pub async fn main() -> ExitCode {
     env_logger::init();
 
     let args = Arc::new(Args::parse());
-    let config = args.config.as_ref().map(|i| i.clone()).unwrap_or(args.root.join("site.toml"));
+    let (format, deny_warnings) = match &args.command {
+        Some(Command::Check { format, deny_warnings }) => (*format, *deny_warnings),
+        _ => (Format::Human, false),
+    };
 
-    SITE_ROOT.set(Arc::new(config.clone())).expect("Failed to set site root");
+    let result = run(args.clone()).await;
 
-    let config = Arc::new(toml::de::from_str::<Config>(&tokio::fs::read_to_string(config).await?)?);
-
-    ARGS.set(Arc::clone(&args)).expect("Failed to set args");
-    CONFIG.set(Arc::clone(&config)).expect("Failed to set config");
+    // In JSON, the error which stopped the build is listed along with everything else
+    if let (Err(err), Format::Json) = (&result, format) {
+        report::error(args.config_path(), err);
+    }
 
-    let mut set = JoinSet::new();
+    let summary = report::summarise(format);
 
-    for page in list_pages().await? {
-        set.spawn(build(page));
+    match result {
+        Err(err) if format == Format::Human => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        },
+        Err(_) => ExitCode::FAILURE,
+        Ok(()) if summary.errors > 0 || (deny_warnings && summary.warnings > 0) => ExitCode::FAILURE,
+        Ok(()) => ExitCode::SUCCESS,
     }
+}
 
-    while let Some(result) = set.join_next().await {
-        result??;
+async fn run(args: Arc<Args>) -> Result<()> {
+    // There is no site to load before it is created
+    if let Some(Command::New { path }) = &args.command {
+        return scaffold::scaffold(path.as_ref().unwrap_or(&args.root)).await;
     }
 
-    Ok(())
+    SITE_ROOT.set(Arc::new(args.config_path().canonicalize()?)).expect("Failed to set site root");
+
+    ARGS.set(Arc::clone(&args)).expect("Failed to set args");
+    load().await?;
+
+    match args.command.clone() {
+        Some(Command::Build { watch: true }) => {
+            let dependencies = build_all().await?;
+            report::summarise(Format::Human);
+            watch::watch(dependencies, |_, _| {}).await
+        },
+        Some(Command::Build { watch: false }) | None => build_all().await.map(|_| ()),
+        Some(Command::Check { .. }) => check_all().await,
+        Some(Command::Clean) => clean().await,
+        Some(Command::New { .. }) => unreachable!("New sites are created before the site is loaded"),
+        #[cfg(feature = "serve")]
+        Some(Command::Serve { port, host, no_reload }) => {
+            let dependencies = build_all().await?;
+            report::summarise(Format::Human);
+            serve::serve(args.root.clone(), host, port, (!no_reload).then_some(dependencies)).await
+        },
+    }
 }
\ No newline at end of file
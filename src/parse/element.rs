@@ -1,138 +1,173 @@
-use std::path::Path;
-use regex::Regex;
 use crate::{
-    parse::Origin,
-    parse::Element,
     parse::Attribute,
+    parse::tokenizer::Spanned,
+    parse::tokenizer::Token,
+    parse::tokenizer::Tokenizer,
     Error,
     BuildError,
-    parse::ParsingContext
 };
 
-impl<Source: AsRef<str> + 'static, File: AsRef<Path> + 'static> ParsingContext<Source, File> {
-    pub(super) fn parse_tag(&mut self, depth: usize) -> crate::Result<Element> {
-        let captures = match self.combined.captures(&self) {
-            Some(captures) => captures,
-            None => return Err(Error::BuildError(BuildError::NotATag))
-        };
+fn is_name_char(char: char) -> bool {
+    char.is_ascii_alphanumeric() || char == '_' || char == '-'
+}
 
-        let open = captures.get(0).unwrap();
-
-        let tag = captures.name("tag").ok_or(Error::BuildError(BuildError::NoTagName))?.as_str().to_owned();
-        let mut attributes = if let Some(attr) = captures.name("attributes") {
-            self.attribute
-                .captures_iter(attr.as_str())
-                .filter_map(|i| {
-                    let value = i.name("double").or_else(|| i.name("single"))?.as_str();
-                    let name = i.name("name")?;
-
-                    Some(Attribute {
-                        name: name.as_str().to_owned(),
-                        value: value[1..value.len() - 1].to_owned(),
-
-                        origin: Origin {
-                            source: self.origin.as_ref().to_path_buf(),
-                            offset: self.range().start + attr.start() + name.start() + 1,
-                            token_length: i.get(0).unwrap().len(),
-                            depth,
-                        },
-                    })
-                })
-                .collect::<Vec<Attribute>>()
-        } else {
-            vec![]
-        };
+impl Tokenizer<'_> {
+    /// The length of the tag or selector name starting at `from`
+    fn name_at(&self, from: usize) -> usize {
+        self.source[from..].find(|char: char| !is_name_char(char)).unwrap_or(self.source.len() - from)
+    }
 
-        (|| -> Option<()> {
-            let capture = captures.name("selector")?;
-            let selectors = self.css_selector_shorthand.captures(capture.as_str())?;
-
-            let classes1 = selectors.name("class1").map(|i| i.as_str().split("."));
-            let classes2 = selectors.name("class2").map(|i| i.as_str().split("."));
-
-            let class = Attribute {
-                name: "class".to_string(),
-                value: {
-                    let mut class = Vec::new();
-                    if let Some(classes) = classes1 { class.extend(classes); }
-                    if let Some(classes) = classes2 { class.extend(classes); }
-                    class.join(" ").trim().to_owned()
-                },
-                origin: Origin {
-                    source: self.origin.as_ref().to_path_buf(),
-                    offset: self.range().start + capture.start(),
-                    token_length: capture.len(),
-                    depth,
-                },
-            };
-
-            if class.value.trim().len() > 0 {
-                attributes.push(class);
+    fn skip_whitespace_from(&self, from: usize) -> usize {
+        let rest = &self.source[from..];
+        from + rest.len() - rest.trim_start().len()
+    }
+
+    /// Parses an opening tag, such as `<div.card#main title="Hi" hidden>`.
+    /// Classes and the id given by the selector shorthand are added after the explicit attributes.
+    pub(super) fn open_tag(&mut self) -> crate::Result<Spanned> {
+        let start = self.position;
+        let name_end = start + 1 + self.name_at(start + 1);
+        let name = self.source[start + 1..name_end].to_lowercase();
+
+        let unterminated = |tokenizer: &Self| Error::BuildError(BuildError::UnexpectedEOF(tokenizer.origin(start..name_end)));
+
+        // The selector shorthand directly follows the name: any number of classes and at most one id
+        let mut classes = Vec::new();
+        let mut id = None;
+        let mut cursor = name_end;
+        while let Some(prefix) = self.source[cursor..].chars().next().filter(|char| *char == '.' || *char == '#') {
+            let len = self.name_at(cursor + 1);
+            let segment = &self.source[cursor + 1..cursor + 1 + len];
+
+            if len == 0 || (prefix == '#' && id.is_some()) {
+                return Err(Error::BuildError(BuildError::BadSelectorList(self.origin(name_end..cursor + 1 + len))));
             }
 
-            if let Some(id) = selectors.name("id") {
-                attributes.push(Attribute {
-                    name: "id".to_string(),
-                    value: id.as_str()[1..].to_owned(),
-                    origin: Origin {
-                        source: self.origin.as_ref().to_path_buf(),
-                        offset: self.range().start + capture.start() + id.start(),
-                        token_length: id.len(),
-                        depth,
-                    },
-                });
+            if prefix == '.' {
+                classes.push(segment.to_owned());
+            } else {
+                id = Some((segment.to_owned(), cursor..cursor + 1 + len));
             }
 
-            Some(())
-        })().ok_or(Error::BuildError(BuildError::BadSelectorList))?;
-
-        if open.as_str().ends_with("/>") {
-            return Ok(Element {
-                attributes,
-                name: tag.to_lowercase(),
-                origin: Origin {
-                    token_length: open.as_str().len(),
-                    source: self.path(),
-                    offset: self.range().start,
-                    depth
-                },
-                body: vec![]
-            });
+            cursor += 1 + len;
         }
+        let selector = name_end..cursor;
+
+        let mut attributes = Vec::new();
+        let self_closing = loop {
+            cursor = self.skip_whitespace_from(cursor);
+            let rest = &self.source[cursor..];
+
+            if rest.is_empty() {
+                return Err(unterminated(self));
+            } else if rest.starts_with('>') {
+                cursor += 1;
+                break false;
+            } else if rest.starts_with("/>") {
+                cursor += 2;
+                break true;
+            } else if rest.starts_with('/') {
+                cursor += 1;
+                continue;
+            }
+
+            let name_len = rest.find(|char: char| char.is_whitespace() || "=>/\"'<".contains(char)).unwrap_or(rest.len());
+            if name_len == 0 {
+                return Err(Error::BuildError(BuildError::InvalidTag(self.origin(cursor..cursor + 1))));
+            }
 
-        let close = (|cx: &ParsingContext<Source, File>| {
-            // Find closing tag by counting opening and closing tags with the same name
-            let mut open_count = 1i64;
-            let source = &self[open.end()..];
-
-            for tag in Regex::new(format!(r#"</?{}"#, &tag).as_str())?
-                .find_iter(source) {
-                open_count += if tag.as_str().starts_with("</") { -1 } else { 1 };
-
-                if open_count <= 0 {
-                    return cx.closing
-                        .captures_at(source, tag.start())
-                        .and_then(|i| i.iter().next())
-                        .flatten()
-                        .ok_or(Error::BuildError(BuildError::NoClosingTag));
+            let attribute_start = cursor;
+            let name = rest[..name_len].to_owned();
+            cursor += name_len;
+
+            // Attributes without a value (`<input disabled>`) are kept with an empty one
+            let mut value = String::new();
+            let mut is_expression = false;
+
+            let after_name = self.skip_whitespace_from(cursor);
+            let is_boolean = !self.source[after_name..].starts_with('=');
+            if !is_boolean {
+                cursor = self.skip_whitespace_from(after_name + 1);
+                let rest = &self.source[cursor..];
+
+                match rest.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let len = rest[1..].find(quote).ok_or_else(|| unterminated(self))?;
+                        value = rest[1..1 + len].to_owned();
+                        cursor += len + 2;
+                    },
+                    Some('{') => {
+                        let end = self.braced(cursor)?;
+                        value = self.source[cursor + 1..end - 1].to_owned();
+                        is_expression = true;
+                        cursor = end;
+                    },
+                    // Unquoted values end at whitespace or the end of the tag
+                    Some(_) => {
+                        let len = rest.char_indices()
+                            .find(|(i, char)| char.is_whitespace() || *char == '>' || rest[*i..].starts_with("/>"))
+                            .map_or(rest.len(), |(i, _)| i);
+                        value = rest[..len].to_owned();
+                        cursor += len;
+                    },
+                    None => return Err(unterminated(self)),
                 }
             }
 
-            return Err(Error::BuildError(BuildError::NoClosingTag));
-        })(&self)?;
-
-        let new_range = self.range().start + open.len()..self.range().start + open.end() + close.start();
-
-        return Ok(Element {
-            attributes,
-            name: tag.to_lowercase(),
-            origin: Origin {
-                token_length: open.as_str().len() + new_range.len() + close.as_str().len(),
-                source: self.path(),
-                offset: self.range().start,
-                depth
-            },
-            body: self.parse_body(new_range, depth + 1)?
-        });
+            attributes.push(Attribute {
+                name,
+                value,
+                is_expression,
+                is_boolean,
+                origin: self.origin(attribute_start..cursor),
+            });
+        };
+
+        if !classes.is_empty() {
+            attributes.push(Attribute {
+                name: "class".to_owned(),
+                value: classes.join(" "),
+                is_expression: false,
+                is_boolean: false,
+                origin: self.origin(selector.clone()),
+            });
+        }
+
+        if let Some((id, span)) = id {
+            attributes.push(Attribute {
+                name: "id".to_owned(),
+                value: id,
+                is_expression: false,
+                is_boolean: false,
+                origin: self.origin(span),
+            });
+        }
+
+        self.position = cursor;
+        Ok(Spanned {
+            token: Token::Open { name, attributes, self_closing },
+            span: start..cursor,
+        })
+    }
+
+    /// Parses a closing tag. Anything between the name and the end of the tag is ignored.
+    pub(super) fn close_tag(&mut self) -> crate::Result<Spanned> {
+        let start = self.position;
+        let name_len = self.name_at(start + 2);
+
+        if !self.source[start + 2..].starts_with(|char: char| char.is_ascii_alphabetic()) {
+            return Err(Error::BuildError(BuildError::InvalidTag(self.origin(start..start + 2))));
+        }
+
+        let name = self.source[start + 2..start + 2 + name_len].to_lowercase();
+        let end = self.source[start..].find('>')
+            .map(|i| start + i + 1)
+            .ok_or_else(|| Error::BuildError(BuildError::UnexpectedEOF(self.origin(start..start + 2 + name_len))))?;
+
+        self.position = end;
+        Ok(Spanned {
+            token: Token::Close { name },
+            span: start..end,
+        })
     }
-}
\ No newline at end of file
+}
@@ -1,104 +1,120 @@
-use std::path::Path;
 use crate::{
-    parse::Origin,
-    parse::Literal,
+    parse::tokenizer::Spanned,
+    parse::tokenizer::Token,
+    parse::tokenizer::Tokenizer,
     Error,
     BuildError,
-    parse::ParsingContext
 };
 
-impl<Source: AsRef<str> + 'static, File: AsRef<Path> + 'static> ParsingContext<Source, File> {
-    pub(super) fn parse_literal(&mut self, depth: usize) -> crate::Result<Literal> {
-        let allowed_quotes = ['"', '\'', '´'];
-        let (modifier, string) = self.find(&allowed_quotes)
-            .map(|i| self.split_at(i + 1))
-            .ok_or(Error::BuildError(BuildError::NotALiteral))?;
-
-        let modifier_length = modifier.len();
-        let modifier = self.legal_string_modifier.captures(modifier)
-            .ok_or(Error::BuildError(BuildError::NotALiteral))?;
-
-        let r#mod = modifier.name("mod").unwrap().as_str();
-        let hash = modifier.name("hash").unwrap().as_str();
-        let quot = modifier.name("quot").unwrap().as_str();
-
-        let literal = if r#mod.contains('r') {
-            if let Some(end) = string.find(&format!("{}{}", quot, "#".repeat(hash.len()))) {
-                Literal {
-                    body: string[..end].to_owned().into_bytes(),
-                    is_byte_string: r#mod.contains('b'),
-                    origin: Origin {
-                        source: self.origin.as_ref().to_path_buf(),
-                        offset: self.range().start + modifier_length,
-                        depth,
-                        token_length: modifier_length + end // TODO: Test
-                    },
-                }
-            } else {
-                return Err(Error::BuildError(BuildError::InvalidSyntax(string.to_string())))
-            }
-        } else if hash.len() > 0 {
-            return Err(Error::BuildError(BuildError::NotALiteral))
+/// Quotes which a literal may be written in
+const QUOTES: [char; 3] = ['"', '\'', '´'];
+
+/// The offset of the quote closing a literal, skipping escaped quotes unless the literal is raw.
+/// As the opening quote might just as well be an apostrophe in prose, the literal must also be closed before the next tag or expression, at the end of a word.
+fn closing_quote(string: &str, quot: char, raw: bool) -> Option<usize> {
+    let mut iter = string.char_indices().peekable();
+
+    while let Some((offset, char)) = iter.next() {
+        let next = iter.peek().map(|(_, char)| *char);
+
+        match char {
+            '\\' if !raw => { iter.next(); },
+            '{' => return None,
+            '<' if next.is_some_and(|next| next.is_ascii_alphabetic() || next == '/' || next == '!') => return None,
+            _ if char == quot && !next.is_some_and(char::is_alphanumeric) => return Some(offset),
+            _ => {},
+        }
+    }
+
+    None
+}
+
+impl Tokenizer<'_> {
+    /// Parses a string literal, such as `"text"`, `b"\x00"` or `r#"raw"#`.
+    /// `None` if the source doesn't start with one, in which case it is text.
+    ///
+    /// A quote only starts a literal if it is closed within the same run of text, so apostrophes and quotes in prose stay as they are,
+    /// as do words such as `b'day`. Only a raw string with hashes, which prose can't be mistaken for, must be closed.
+    pub(super) fn literal(&mut self) -> crate::Result<Option<Spanned>> {
+        let rest = self.rest();
+
+        let modifier = ["rb", "br", "r", "b", ""].into_iter()
+            .find(|modifier| rest.starts_with(modifier))
+            .unwrap_or_default();
+        let hash = rest[modifier.len()..].len() - rest[modifier.len()..].trim_start_matches('#').len();
+
+        let quote_at = modifier.len() + hash;
+        let Some(quot) = rest[quote_at..].chars().next().filter(|char| QUOTES.contains(char)) else {
+            return Ok(None);
+        };
+
+        // Hashes are only meaningful for raw strings
+        if hash > 0 && !modifier.contains('r') {
+            return Ok(None);
+        }
+
+        let is_byte = modifier.contains('b');
+        let quote_origin = self.origin(self.position + quote_at..self.position + quote_at + quot.len_utf8());
+        let string = &rest[quote_at + quot.len_utf8()..];
+
+        let is_raw = modifier.contains('r');
+        let end = match hash {
+            0 => closing_quote(string, quot, is_raw),
+            _ => Some(string.find(&format!("{}{}", quot, "#".repeat(hash)))
+                .ok_or_else(|| Error::BuildError(BuildError::InvalidSyntax(quote_origin.clone())))?),
+        };
+
+        let Some(end) = end else {
+            return Ok(None);
+        };
+
+        let (body, len) = if is_raw {
+            (string.as_bytes()[..end].to_vec(), end + quot.len_utf8() + hash)
         } else {
             let mut body = Vec::new();
-            let mut iter = string.chars();
-
-            let is_byte = r#mod.contains('b');
+            let mut iter = string[..end].chars().peekable();
 
             while let Some(next) = iter.next() {
-                if next == '\\' {
-                    match iter.next() {
-                        Some('t') => body.push(b'\t'),
-                        Some('n') => body.push(b'\n'),
-                        Some('r') => body.push(b'\r'),
-                        Some('0') => body.push(b'\0'),
-                        Some('b') => { body.pop(); }
-                        Some('x') if is_byte => {
-                            let mut hex = String::new();
-                            let allowed = "0123456789abcdefABCDEF";
-                            while let Some(char) = iter.next() {
-                                if allowed.contains(char) {
-                                    hex.push(char);
-                                } else {
-                                    body.extend(self.parse_hex(hex));
-                                    break;
-                                }
-                            }
-                        }
-                        Some('u') if !is_byte => {
-                            let char = iter
-                                .by_ref()
-                                .take_while(|i| *i != '}')
-                                .collect::<String>()[1..]
-                                .to_owned();
-
-                            let bytes = self.parse_hex(format!("{:0>8}", &char));
-                            let char = char::from_u32(u32::from_be_bytes(bytes[0..4].try_into().unwrap()))
-                                .ok_or(Error::BuildError(BuildError::InvalidCharacterCode(char)))?;
-
-                            body.extend(char.to_string().bytes());
+                if next != '\\' {
+                    body.extend(next.to_string().bytes());
+                    continue;
+                }
+
+                match iter.next() {
+                    Some('t') => body.push(b'\t'),
+                    Some('n') => body.push(b'\n'),
+                    Some('r') => body.push(b'\r'),
+                    Some('0') => body.push(b'\0'),
+                    Some('b') => { body.pop(); }
+                    Some('x') if is_byte => {
+                        let mut hex = String::new();
+                        while let Some(char) = iter.next_if(|char| char.is_ascii_hexdigit() && hex.len() < 2) {
+                            hex.push(char);
                         }
-                        Some(char) => body.extend(char.to_string().bytes()),
-                        _ => return Err(Error::BuildError(BuildError::UnexpectedEOF))
+
+                        body.push(u8::from_str_radix(&hex, 16)
+                            .map_err(|_| Error::BuildError(BuildError::InvalidCharacterCode { code: hex, origin: quote_origin.clone() }))?);
                     }
-                } else if next.to_string().eq(quot) { break; } else {
-                    body.extend(next.to_string().bytes())
+                    Some('u') if !is_byte => {
+                        let code = match iter.next_if(|char| *char == '{') {
+                            Some(_) => iter.by_ref().take_while(|char| *char != '}').collect::<String>(),
+                            None => String::new(),
+                        };
+
+                        let char = u32::from_str_radix(&code, 16).ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| Error::BuildError(BuildError::InvalidCharacterCode { code, origin: quote_origin.clone() }))?;
+
+                        body.extend(char.to_string().bytes());
+                    }
+                    Some(char) => body.extend(char.to_string().bytes()),
+                    None => return Err(Error::BuildError(BuildError::UnexpectedEOF(quote_origin))),
                 }
             }
 
-            Literal {
-                is_byte_string: is_byte,
-                origin: Origin {
-                    source: self.origin.as_ref().to_path_buf(),
-                    offset: self.range().start + modifier_length,
-                    depth,
-                    token_length: modifier_length + body.len() + 1
-                },
-                body,
-            }
+            (body, end + quot.len_utf8())
         };
 
-        return Ok(literal);
+        Ok(Some(self.take(Token::Literal { body, is_byte_string: is_byte }, quote_at + quot.len_utf8() + len)))
     }
-
-}
\ No newline at end of file
+}
@@ -1,18 +1,18 @@
 mod literal;
 mod element;
 mod expression;
+pub mod tokenizer;
 
 use std::{
-    collections::VecDeque,
     fmt::Debug,
     fmt::Formatter,
-    ops::Deref,
     ops::Range,
     path::Path,
     path::PathBuf
 };
-use regex::Regex;
+use rune::Any;
 use crate::error::*;
+use crate::parse::tokenizer::{Spanned, Token, Tokenizer};
 
 #[derive(Clone)]
 pub struct Element {
@@ -22,7 +22,9 @@ pub struct Element {
     pub origin: Origin,
 }
 
-#[derive(Debug, Clone)]
+/// Where a node was found. Passed to content type handlers, which attribute the elements they produce to it.
+#[derive(Debug, Clone, Any)]
+#[rune(item = ::jcake_ssg)]
 pub struct Origin {
     pub source: PathBuf,
     pub offset: usize,
@@ -34,6 +36,15 @@ impl Origin {
     pub fn len(&self) -> usize {
         self.token_length
     }
+
+    /// The line and column the origin starts at within its source, both counted from 1.
+    /// `None` if the source doesn't contain the offset, as happens if it has changed since it was parsed.
+    pub fn position(&self, source: &str) -> Option<(usize, usize)> {
+        let before = source.get(..self.offset)?;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        Some((before.matches('\n').count() + 1, before[line_start..].chars().count() + 1))
+    }
 }
 
 impl Debug for Element {
@@ -43,7 +54,7 @@ impl Debug for Element {
             write!(f, " {}=\"{}\"", &attr.name, &attr.value)?;
         }
 
-        if self.body.len() > 0 {
+        if !self.body.is_empty() {
             write!(f, ">")?;
 
             for i in self.body.iter() {
@@ -99,16 +110,6 @@ pub enum Body {
     Literal(Literal),
 }
 
-impl Body {
-    pub fn origin(&self) -> &Origin {
-        match &self {
-            Self::Element(el) => &el.origin,
-            Self::Expression(expr) => &expr.origin,
-            Self::Literal(lit) => &lit.origin,
-        }
-    }
-}
-
 impl Debug for Body {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -123,45 +124,38 @@ impl Debug for Body {
 pub struct Attribute {
     pub name: String,
     pub value: String,
-    // Expression
+    /// Whether the value was given in braces (`title={page.title}`) and must be evaluated before use
+    pub is_expression: bool,
+    /// Whether the attribute was written without a value (`<input disabled>`), rather than with an empty one (`alt=""`)
+    pub is_boolean: bool,
     pub origin: Origin,
 }
 
+/// Elements which never have a body or a closing tag, regardless of whether the source self-closes them
+pub const VOID_ELEMENTS: [&str; 13] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr"];
+
+/// Elements within which whitespace is significant, so text consisting only of it is kept as it is
+const PREFORMATTED_ELEMENTS: [&str; 2] = ["pre", "textarea"];
+
+/// Elements which whitespace next to or just within doesn't show: block-level HTML, and the elements which structure a page rather than render inline.
+/// Whitespace between `<condition>`s is dropped along with it, as anything between them would end the chain.
+const BLOCK_ELEMENTS: [&str; 47] = [
+    "address", "article", "aside", "blockquote", "body", "dd", "details", "dialog", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hgroup", "hr", "html", "li", "main", "nav", "ol", "p", "pre", "section", "table", "tbody", "td",
+    "tfoot", "th", "thead", "tr", "ul",
+    "condition", "component", "fragment", "page", "template",
+];
+
+/// Builds the element tree of a source from its [`tokens`](Tokenizer), matching each closing tag to the innermost open element
 #[derive(Clone)]
 pub struct ParsingContext<Source: AsRef<str> + 'static, Origin: AsRef<Path> + 'static> {
-    // HTML regex
-    pub tag_name: Regex,
-    pub css_selector_shorthand: Regex,
-    pub attribute: Regex,
-    pub open_or_closing_tag: Regex,
-    pub combined: Regex,
-    pub garbage: Regex,
-    pub closing: Regex,
-
-    // Literal regex
-    pub legal_string_modifier: Regex,
-    // pub escaped_string: Regex,
-
     source: Source,
     origin: Origin,
-    range_stack: VecDeque<Range<usize>>
 }
 
 impl<Source: AsRef<str> + 'static, File: AsRef<Path> + 'static> ParsingContext<Source, File> {
     pub fn new(source: Source, origin: File) -> Result<Self> {
         Ok(Self {
-            tag_name: Regex::new(r#"^[a-zA-Z][a-zA-Z0-9_-]*$"#)?,
-            css_selector_shorthand: Regex::new(r#"^(?<class1>\.[a-zA-Z][a-zA-Z0-9_-]*)*(?<id>#[a-zA-Z][a-zA-Z0-9_-]*)?(?<class2>\.[a-zA-Z][a-zA-Z0-9_-]*)*$"#)?,
-            attribute: Regex::new(r#"(?<name>\w+)(?:=\s*(?<double>"[^"]*")|(?<single>'[^']*'))?"#)?,
-            open_or_closing_tag: Regex::new(r#"</?(?<tag>[a-zA-Z][a-zA-Z0-9_-]*)"#)?,
-            combined: Regex::new(r#"^<\s*(?<tag>[a-zA-Z][a-zA-Z0-9_-]*)\s*(?<selector>(?:\.[a-zA-Z][a-zA-Z0-9_-]*)*(?:#[a-zA-Z][a-zA-Z0-9_-]*)?(?:\.[a-zA-Z][a-zA-Z0-9_-]*)*)(?<attributes>(?:\s+\w+(?:\s*=\s*("[^"]*")|('[^']*'))?)*)\s*/?>"#)?,
-            closing: Regex::new(r#"</\s*[a-zA-Z][a-zA-Z0-9_-]*\s*>"#)?,
-            garbage: Regex::new(r#"^(?:[\s\n]+|<!--.*?-->)"#)?,
-
-            legal_string_modifier: Regex::new(r#"^(?<mod>r?b?|b?r?)(?<hash>#{0,256})(?<quot>["'`])"#)?, // You would have to manually check that the hashtags always follow a modifier
-            // escaped_string: ,
-
-            range_stack: vec![0..source.as_ref().len()].into_iter().collect(),
             source,
             origin,
         })
@@ -171,90 +165,255 @@ impl<Source: AsRef<str> + 'static, File: AsRef<Path> + 'static> ParsingContext<S
         self.origin.as_ref().to_path_buf()
     }
 
-    pub fn range(&self) -> &Range<usize> {
-        self.range_stack.get(self.range_stack.len() - 1).unwrap()
+    fn origin_at(&self, span: Range<usize>, depth: usize) -> Origin {
+        Origin {
+            source: self.path(),
+            offset: span.start,
+            depth,
+            token_length: span.len(),
+        }
     }
 
-    pub fn range_mut(&mut self) -> &mut Range<usize> {
-        self.range_stack.get_mut(self.range_stack.len() - 1).unwrap()
+    /// Markup passed through as it is, as an `<escape mode="raw">`
+    fn raw(&self, span: Range<usize>, depth: usize) -> Body {
+        self.markup(self.source.as_ref().as_bytes()[span.clone()].to_vec(), span, depth)
     }
 
-    pub fn skip_whitespace(&mut self) -> &mut Self {
-        while let Some(ignored) = self.garbage.find(&self) {
-            if ignored.len() <= 0 { return self; }
-
-            self.range_mut().start += ignored.len();
-        }
-
-        return self;
+    /// Markup standing in for the given span of source
+    fn markup(&self, markup: Vec<u8>, span: Range<usize>, depth: usize) -> Body {
+        Body::Element(Element {
+            name: "escape".to_owned(),
+            attributes: vec![Attribute {
+                name: "mode".to_owned(),
+                value: "raw".to_owned(),
+                is_expression: false,
+                is_boolean: false,
+                origin: self.origin_at(span.clone(), depth),
+            }],
+            body: vec![Body::Literal(Literal {
+                body: markup,
+                origin: self.origin_at(span.clone(), depth + 1),
+                is_byte_string: false,
+            })],
+            origin: self.origin_at(span, depth),
+        })
     }
 
     pub fn parse(&mut self) -> Result<Element> {
-        Ok(Element {
-            origin: Origin {
-                depth: 0,
-                offset: 0,
-                source: self.path(),
-                token_length: self.len(),
-            },
+        let source = self.source.as_ref();
+
+        // The innermost open element is last. While an element is open, its origin only spans its opening tag.
+        let mut stack = vec![Element {
+            origin: self.origin_at(0..source.len(), 0),
             attributes: vec![Attribute {
                 name: "origin".to_string(),
                 value: self.path().to_str().unwrap().to_string(),
-                origin: Origin {
-                    depth: 0,
-                    offset: 0,
-                    source: self.path(),
-                    token_length: self.len(),
-                }
+                is_expression: false,
+                is_boolean: false,
+                origin: self.origin_at(0..source.len(), 0),
             }],
             name: "fragment".to_owned(),
-            body: self.parse_body(self.range().clone(), 1)?,
-        })
-    }
+            body: vec![],
+        }];
+
+        // The tokenizer splits text into words, which are joined back together before being added
+        let mut text: Option<Range<usize>> = None;
+
+        for token in Tokenizer::new(source, self.origin.as_ref()) {
+            let Spanned { token, span } = token?;
+
+            // Only adjacent words are joined, so text either side of a comment stays apart
+            if let Some(words) = text.take_if(|words| !matches!(token, Token::Text) || words.end != span.start) {
+                self.push_text(&mut stack, words);
+            }
+
+            if let Token::Text = token {
+                text = Some(text.map_or(span.clone(), |text| text.start..span.end));
+                continue;
+            }
 
-    fn parse_body(&mut self, range: Range<usize>, depth: usize) -> Result<Vec<Body>> {
-        let mut body = Vec::new();
+            let depth = stack.len();
+            let parent = stack.last_mut().unwrap();
+
+            match token {
+                Token::Text => unreachable!("Text is added once the words following it end"),
+                Token::Literal { body, is_byte_string } => parent.body.push(Body::Literal(Literal {
+                    body,
+                    origin: self.origin_at(span, depth),
+                    is_byte_string,
+                })),
+                Token::Expression(body) => parent.body.push(Body::Expression(Expression {
+                    body,
+                    origin: self.origin_at(span, depth),
+                })),
+                Token::Raw => {
+                    let raw = self.raw(span, depth);
+                    parent.body.push(raw);
+                },
+                Token::Open { name, mut attributes, self_closing } => {
+                    for attr in attributes.iter_mut() {
+                        attr.origin.depth = depth;
+                    }
+
+                    let element = Element {
+                        origin: self.origin_at(span, depth),
+                        attributes,
+                        body: vec![],
+                        name,
+                    };
+
+                    if self_closing || VOID_ELEMENTS.contains(&element.name.as_str()) {
+                        parent.body.push(Body::Element(element));
+                    } else {
+                        stack.push(element);
+                    }
+                },
+                Token::Close { name } => {
+                    if stack.len() > 1 && stack.last().is_some_and(|open| open.name == name) {
+                        let mut element = stack.pop().unwrap();
+                        element.origin.token_length = span.end - element.origin.offset;
+                        collapse(&stack, &mut element);
+                        stack.last_mut().unwrap().body.push(Body::Element(element));
+                    } else if VOID_ELEMENTS.contains(&name.as_str()) {
+                        continue;
+                    } else if stack[1..].iter().any(|open| open.name == name) {
+                        // The element it closes is still open, so an element within it must have been left unclosed
+                        return Err(unclosed(stack.pop().unwrap()));
+                    } else {
+                        return Err(Error::BuildError(BuildError::UnexpectedClosingTag { tag: name, origin: self.origin_at(span, depth) }));
+                    }
+                },
+            }
+        }
 
-        self.range_stack.push_back(range);
+        if let Some(text) = text.take() {
+            self.push_text(&mut stack, text);
+        }
 
-        while let Some(child) = self.try_parse_any(depth)? {
-            self.range_mut().start += child.origin().len();
-            body.push(child);
+        if stack.len() > 1 {
+            return Err(unclosed(stack.pop().unwrap()));
         }
 
-        self.range_stack.pop_back().unwrap();
+        let mut fragment = stack.pop().unwrap();
+        fragment.origin.token_length = source.len();
+        collapse(&stack, &mut fragment);
 
-        Ok(body)
+        Ok(fragment)
     }
 
-    fn try_parse_any(&mut self, depth: usize) -> Result<Option<Body>> {
-        self.skip_whitespace();
+    /// Adds text to the innermost open element. Outside of preformatted elements, text which is only whitespace is collapsed to a single space.
+    fn push_text(&self, stack: &mut [Element], text: Range<usize>) {
+        let raw = if !preformatted(stack) && self.source.as_ref()[text.clone()].trim().is_empty() {
+            self.markup(b" ".to_vec(), text, stack.len())
+        } else {
+            self.raw(text, stack.len())
+        };
 
-        match self.parse_expr(depth) {
-            Ok(expr) => return Ok(Some(Body::Expression(expr))),
-            Err(Error::BuildError(BuildError::NotAnExpression)) => (),
-            Err(err) => return Err(err)
-        }
+        stack.last_mut().unwrap().body.push(raw);
+    }
+}
 
-        match self.parse_literal(depth) {
-            Ok(lit) => return Ok(Some(Body::Literal(lit))),
-            Err(Error::BuildError(BuildError::NotALiteral)) => (),
-            Err(err) => return Err(err)
-        }
+fn preformatted(stack: &[Element]) -> bool {
+    stack.iter().any(|open| PREFORMATTED_ELEMENTS.contains(&open.name.as_str()))
+}
 
-        match self.parse_tag(depth) {
-            Ok(tag) => return Ok(Some(Body::Element(tag))),
-            Err(Error::BuildError(BuildError::NotATag)) => (),
-            Err(err) => return Err(err)
-        }
+/// Whether the node is whitespace collapsed by [`ParsingContext::push_text`]
+fn is_space(body: &Body) -> bool {
+    matches!(body, Body::Element(el) if el.name == "escape" && matches!(&el.body[..], [Body::Literal(lit)] if lit.body == b" "))
+}
 
-        return Ok(None);
+/// Drops the collapsed whitespace within a closed element which can't show: at the start or end of a block-level element, or next to one
+fn collapse(stack: &[Element], element: &mut Element) {
+    if preformatted(stack) || PREFORMATTED_ELEMENTS.contains(&element.name.as_str()) {
+        return;
     }
+
+    let is_block = BLOCK_ELEMENTS.contains(&element.name.as_str());
+    let hidden = |sibling: Option<&Body>| match sibling {
+        Some(Body::Element(sibling)) => BLOCK_ELEMENTS.contains(&sibling.name.as_str()),
+        Some(_) => false,
+        None => is_block,
+    };
+
+    // The body is rebuilt in one pass, so a space is compared against what was kept before it and what follows it
+    let mut body = std::mem::take(&mut element.body).into_iter().peekable();
+    while let Some(child) = body.next() {
+        if !(is_space(&child) && (hidden(element.body.last()) || hidden(body.peek()))) {
+            element.body.push(child);
+        }
+    }
+}
+
+/// Unclosed elements are reported at their opening tag
+fn unclosed(element: Element) -> Error {
+    Error::BuildError(BuildError::NoClosingTag {
+        tag: element.name,
+        origin: element.origin,
+    })
 }
 
-impl<Source: AsRef<str> + 'static, Origin: AsRef<Path> + 'static> Deref for ParsingContext<Source, Origin> {
-    type Target = str;
-    fn deref(&self) -> &Self::Target {
-        &self.source.as_ref()[self.range().clone()]
+#[cfg(test)]
+mod test {
+    use crate::{BuildError, Error};
+    use crate::parse::{Body, Element, ParsingContext};
+
+    fn parse(source: &str) -> crate::Result<Element> {
+        ParsingContext::new(source.to_owned(), "test.html")?.parse()
     }
-}
\ No newline at end of file
+
+    /// The tree in brief: elements as their name followed by their children in brackets, raw markup and literals as their text
+    fn outline(body: &[Body]) -> String {
+        body.iter()
+            .map(|child| match child {
+                Body::Element(el) if el.name == "escape" && el.attributes.iter().any(|attr| attr.value == "raw") => match &el.body[..] {
+                    [Body::Literal(lit)] => format!("`{}`", String::from_utf8_lossy(&lit.body)),
+                    _ => panic!("Expected raw markup to hold a single literal"),
+                },
+                Body::Element(el) if el.body.is_empty() => el.name.clone(),
+                Body::Element(el) => format!("{}[{}]", el.name, outline(&el.body)),
+                Body::Expression(expr) => format!("{{{}}}", expr.body),
+                Body::Literal(lit) => format!("{:?}", String::from_utf8_lossy(&lit.body)),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_parse() {
+        let tree = |source: &str| outline(&parse(source).unwrap().body);
+
+        assert_eq!(tree(r#"<b><br><abbr>"x"</abbr></b><a href="/">"y"</a>"#), r#"b[br abbr["x"]] a["y"]"#);
+        assert_eq!(tree("<p>Tom &amp; Jerry <b>{name}</b>!</p>\n<p>\n    \"Hi\"\n</p>"), r#"p[`Tom &amp; Jerry ` b[{name}] `!`] p["Hi"]"#);
+        assert_eq!(tree("<!-- <div> --><p><!----></p><pre>  <!-- a -->\n</pre>"), "p pre[`  ` `\n`]");
+        assert_eq!(tree("<!DOCTYPE html><svg><![CDATA[a < b]]></svg>"), "`<!DOCTYPE html>` svg[`<![CDATA[a < b]]>`]");
+        assert_eq!(tree(r#"<script>if (a</b && "{") {}</script><STYLE>p > a {}</Style >"#), r#"script[`if (a</b && "{") {}`] style[`p > a {}`]"#);
+        assert_eq!(tree(r#"<img src="a.png"></img><input disabled><p/>"#), "img input p");
+        assert_eq!(tree("<p>'90s</p><p>{a}'s \"b\"</p><p> <b>\"c\"</b>\n<i />\n</p>"), r#"p[`'90s`] p[{a} `'s ` "b"] p[b["c"] ` ` i]"#);
+        assert_eq!(tree(r##"<p>b"\x41" r#"a"b"# r"\n"</p><p>b'day</p><p>r'n'roll b"a</p>"##), r##"p["A" ` ` "a\"b" ` ` "\\n"] p[`b'day`] p[`r'n'roll b"a`]"##);
+
+        let input = parse(r#"<input.a#b type=text value = 'x' data-x={1 + 1} disabled/>"#).unwrap();
+        let Body::Element(input) = &input.body[0] else { panic!("Expected an element") };
+        let attributes = input.attributes.iter()
+            .map(|attr| (attr.name.as_str(), attr.value.as_str(), attr.is_expression))
+            .collect::<Vec<_>>();
+
+        assert_eq!(attributes, [("type", "text", false), ("value", "x", false), ("data-x", "1 + 1", true), ("disabled", "", false), ("class", "a", false), ("id", "b", false)]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        match parse("<div>\n  <p>\"a\"\n</div>") {
+            Err(Error::BuildError(BuildError::NoClosingTag { tag, origin })) => assert_eq!((tag.as_str(), origin.offset, origin.len()), ("p", 8, 3)),
+            _ => panic!("Expected an unclosed element"),
+        }
+
+        assert!(matches!(parse("<div>"), Err(Error::BuildError(BuildError::NoClosingTag { tag, .. })) if tag == "div"));
+        assert!(matches!(parse("<p></div>"), Err(Error::BuildError(BuildError::UnexpectedClosingTag { tag, .. })) if tag == "div"));
+        assert!(matches!(parse("<script>a"), Err(Error::BuildError(BuildError::NoClosingTag { tag, .. })) if tag == "script"));
+        assert!(matches!(parse("<p>{a</p>"), Err(Error::BuildError(BuildError::BracketMismatch(_)))));
+        assert!(matches!(parse("<!-- a"), Err(Error::BuildError(BuildError::UnexpectedEOF(_)))));
+        assert!(matches!(parse(r##"<p>r#"a</p>"##), Err(Error::BuildError(BuildError::InvalidSyntax(_)))));
+        assert!(matches!(parse("<ul><li>a<li>b</ul>"), Err(Error::BuildError(BuildError::NoClosingTag { tag, .. })) if tag == "li"));
+        assert!(matches!(parse("<p.a#b#c>"), Err(Error::BuildError(BuildError::BadSelectorList(_)))));
+    }
+}
@@ -0,0 +1,217 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use crate::{
+    parse::Attribute,
+    parse::Origin,
+    Error,
+    BuildError,
+};
+
+/// Elements whose content is raw text, running up to their closing tag without any tags, literals or expressions within it
+pub const RAW_TEXT_ELEMENTS: [&str; 3] = ["escape", "script", "style"];
+
+#[derive(Debug, Clone)]
+pub enum Token {
+    /// Anything outside of tags, literals and expressions. It is passed through as markup.
+    Text,
+    Literal { body: Vec<u8>, is_byte_string: bool },
+    /// The body of an expression, without its braces
+    Expression(String),
+    /// Attributes from the selector shorthand follow those given explicitly
+    Open { name: String, attributes: Vec<Attribute>, self_closing: bool },
+    Close { name: String },
+    /// Markup which is passed through as it is: doctypes, CDATA sections and the content of `<script>` and `<style>`
+    Raw,
+}
+
+/// A token, along with the range of the source it was read from
+#[derive(Debug, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub span: Range<usize>,
+}
+
+/// Splits a source into tokens in a single pass. Comments are skipped wherever they appear.
+/// The tokenizer doesn't match opening and closing tags, except to find the end of raw-text elements.
+pub struct Tokenizer<'a> {
+    pub(super) source: &'a str,
+    pub(super) path: PathBuf,
+    pub(super) position: usize,
+    /// The name and span of the opening tag of the raw-text element whose content comes next
+    raw_text: Option<(String, Range<usize>)>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(source: &'a str, path: &Path) -> Self {
+        Self {
+            source,
+            path: path.to_path_buf(),
+            position: 0,
+            raw_text: None,
+        }
+    }
+
+    pub(super) fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    pub(super) fn origin(&self, span: Range<usize>) -> Origin {
+        Origin {
+            source: self.path.clone(),
+            offset: span.start,
+            depth: 0,
+            token_length: span.len(),
+        }
+    }
+
+    /// Consumes `len` bytes, returning the token spanning them
+    pub(super) fn take(&mut self, token: Token, len: usize) -> Spanned {
+        let span = self.position..self.position + len;
+        self.position = span.end;
+
+        Spanned { token, span }
+    }
+
+    /// The offset of the end of the first occurrence of `pattern` from the current position
+    fn find_end(&self, from: usize, pattern: &str) -> Option<usize> {
+        self.source[from..].find(pattern).map(|i| from + i + pattern.len())
+    }
+
+    /// Where the closing tag of the raw-text element named `name` starts, matched without regard to case
+    fn find_closing(&self, name: &str) -> Option<usize> {
+        let mut from = self.position;
+
+        while let Some(index) = self.source[from..].find("</") {
+            let start = from + index;
+            let after = start + 2 + name.len();
+
+            if self.source.get(start + 2..after).is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+                && self.source[after..].starts_with(|char: char| char.is_whitespace() || char == '>') {
+                return Some(start);
+            }
+
+            from = start + 2;
+        }
+
+        None
+    }
+
+    fn raw_text(&mut self, name: String, open: Range<usize>) -> crate::Result<Option<Spanned>> {
+        let end = self.find_closing(&name).ok_or_else(|| Error::BuildError(BuildError::NoClosingTag {
+            tag: name.clone(),
+            origin: self.origin(open),
+        }))?;
+
+        if end == self.position {
+            return Ok(None);
+        }
+
+        let content = &self.source[self.position..end];
+
+        // The content of an escape is text, which it escapes itself
+        let token = if name == "escape" {
+            Token::Literal { body: content.as_bytes().to_vec(), is_byte_string: false }
+        } else {
+            Token::Raw
+        };
+
+        Ok(Some(self.take(token, end - self.position)))
+    }
+
+    /// Skips a comment or an unrecognised `<!...>` declaration. Returns a token for doctypes and CDATA sections, which are kept.
+    fn declaration(&mut self) -> crate::Result<Option<Spanned>> {
+        let rest = self.rest();
+        let unterminated = |tokenizer: &Self, len: usize| Error::BuildError(BuildError::UnexpectedEOF(tokenizer.origin(tokenizer.position..tokenizer.position + len)));
+
+        if rest.starts_with("<!--") {
+            self.position = self.find_end(self.position + 4, "-->").ok_or_else(|| unterminated(self, 4))?;
+            return Ok(None);
+        }
+
+        if rest.starts_with("<![CDATA[") {
+            let end = self.find_end(self.position + 9, "]]>").ok_or_else(|| unterminated(self, 9))?;
+            return Ok(Some(self.take(Token::Raw, end - self.position)));
+        }
+
+        let end = self.find_end(self.position, ">").ok_or_else(|| unterminated(self, 2))?;
+        if rest.get(..9).is_some_and(|start| start.eq_ignore_ascii_case("<!doctype")) {
+            return Ok(Some(self.take(Token::Raw, end - self.position)));
+        }
+
+        self.position = end;
+        Ok(None)
+    }
+
+    /// A run of whitespace, or of anything else up to the next whitespace, tag or expression.
+    /// Text is split at whitespace so that a literal may start any word.
+    fn text(&mut self) -> Spanned {
+        let rest = self.rest();
+
+        let whitespace = rest.len() - rest.trim_start().len();
+        if whitespace > 0 {
+            return self.take(Token::Text, whitespace);
+        }
+
+        let bytes = rest.as_bytes();
+        let mut end = 1;
+        while end < bytes.len() {
+            match bytes[end] {
+                b'{' => break,
+                byte if byte.is_ascii_whitespace() => break,
+                b'<' if bytes.get(end + 1).is_some_and(|next| next.is_ascii_alphabetic() || *next == b'/' || *next == b'!') => break,
+                _ => end += 1,
+            }
+        }
+
+        self.take(Token::Text, end)
+    }
+
+    fn next_token(&mut self) -> crate::Result<Option<Spanned>> {
+        if let Some((name, open)) = self.raw_text.take() {
+            if let Some(token) = self.raw_text(name, open)? {
+                return Ok(Some(token));
+            }
+        }
+
+        loop {
+            let rest = self.rest();
+            let Some(first) = rest.chars().next() else {
+                return Ok(None);
+            };
+
+            let second = rest[first.len_utf8()..].chars().next();
+
+            return Ok(Some(match (first, second) {
+                ('<', Some('!')) => match self.declaration()? {
+                    Some(token) => token,
+                    None => continue,
+                },
+                ('<', Some('/')) => self.close_tag()?,
+                ('<', Some(next)) if next.is_ascii_alphabetic() => {
+                    let token = self.open_tag()?;
+
+                    if let Token::Open { name, self_closing: false, .. } = &token.token {
+                        if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                            self.raw_text = Some((name.clone(), token.span.clone()));
+                        }
+                    }
+
+                    token
+                },
+                ('{', _) => self.expression()?,
+                _ => match self.literal()? {
+                    Some(token) => token,
+                    None => self.text(),
+                },
+            }));
+        }
+    }
+}
+
+impl Iterator for Tokenizer<'_> {
+    type Item = crate::Result<Spanned>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().transpose()
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use serde::Serialize;
+use crate::error::*;
+use crate::parse::Origin;
+
+/// Problems found while building, reported together once the build has finished rather than as they are found
+static PROBLEMS: Mutex<Vec<Problem>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+/// How problems are printed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Grouped by file, pointing at the source of each problem
+    #[default]
+    Human,
+    /// A JSON array of problems, for editors and CI
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    /// Identifies the kind of lint which found the problem. Problems found by building have none.
+    pub code: Option<&'static str>,
+    /// The file the problem was found while building. The origin may point elsewhere, such as at a template the file uses.
+    pub file: PathBuf,
+    pub message: String,
+    pub origin: Option<Origin>,
+    pub hint: Option<String>,
+}
+
+impl Problem {
+    pub fn from_error(severity: Severity, file: PathBuf, err: &Error) -> Self {
+        match err.located() {
+            Some((message, origin, hint)) => Self { severity, code: None, file, message, origin: Some(origin.clone()), hint },
+            None => Self { severity, code: None, file, message: err.to_string(), origin: None, hint: None },
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{}[{}]: ", self.severity, code)?,
+            None => write!(f, "{}: ", self.severity)?,
+        }
+
+        match &self.origin {
+            Some(origin) => Diagnostic { message: self.message.clone(), origin, hint: self.hint.clone() }.fmt(f),
+            None => match &self.hint {
+                Some(hint) => write!(f, "{}\n = hint: {}", self.message, hint),
+                None => f.write_str(&self.message),
+            }
+        }
+    }
+}
+
+/// How many problems of each kind were reported
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+/// Records a problem, to be printed once the build has finished
+pub fn report(problem: Problem) {
+    PROBLEMS.lock().unwrap().push(problem);
+}
+
+/// Records a problem which doesn't stop the file from being built
+pub fn warning(file: impl Into<PathBuf>, message: impl Display) {
+    report(Problem {
+        severity: Severity::Warning,
+        code: None,
+        file: file.into(),
+        message: message.to_string(),
+        origin: None,
+        hint: None,
+    });
+}
+
+/// Records a file which failed to build. The build carries on with the remaining files.
+pub fn error(file: impl Into<PathBuf>, err: &Error) {
+    report(Problem::from_error(Severity::Error, file.into(), err));
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+/// Lists the problems grouped by file, with errors before warnings, followed by how many there were
+pub fn format(problems: &[Problem]) -> String {
+    let mut files = BTreeMap::<&PathBuf, Vec<&Problem>>::new();
+    for problem in problems {
+        files.entry(&problem.file).or_default().push(problem);
+    }
+
+    let mut out = String::new();
+    for (file, problems) in files.iter_mut() {
+        problems.sort_by_key(|problem| problem.severity);
+
+        out.push_str(&format!("{}:\n", display_path(file)));
+        for problem in problems {
+            out.push_str(&format!("{}\n", problem));
+        }
+        out.push('\n');
+    }
+
+    let summary = summary(problems);
+    out.push_str(&format!("{} and {} in {}", plural(summary.errors, "error"), plural(summary.warnings, "warning"), plural(files.len(), "file")));
+
+    out
+}
+
+#[derive(Serialize)]
+struct Location {
+    path: String,
+    line: usize,
+    column: usize,
+    length: usize,
+}
+
+#[derive(Serialize)]
+struct JsonProblem<'a> {
+    severity: Severity,
+    code: Option<&'a str>,
+    file: String,
+    message: &'a str,
+    hint: Option<&'a str>,
+    location: Option<Location>,
+}
+
+/// Lists the problems as a JSON array. Locations are only given if their source can still be read.
+pub fn json(problems: &[Problem]) -> String {
+    let problems = problems.iter()
+        .map(|problem| JsonProblem {
+            severity: problem.severity,
+            code: problem.code,
+            file: display_path(&problem.file),
+            message: &problem.message,
+            hint: problem.hint.as_deref(),
+            location: problem.origin.as_ref().and_then(|origin| {
+                let (line, column) = origin.position(&std::fs::read_to_string(&origin.source).ok()?)?;
+                Some(Location { path: display_path(&origin.source), line, column, length: origin.len() })
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_string_pretty(&problems).expect("Failed to serialise problems")
+}
+
+fn summary(problems: &[Problem]) -> Summary {
+    Summary {
+        errors: problems.iter().filter(|problem| problem.severity == Severity::Error).count(),
+        warnings: problems.iter().filter(|problem| problem.severity == Severity::Warning).count(),
+    }
+}
+
+/// Prints every problem reported so far and forgets them. In human form, nothing is printed if there were none.
+pub fn summarise(format: Format) -> Summary {
+    let problems = std::mem::take(&mut *PROBLEMS.lock().unwrap());
+
+    match format {
+        Format::Human if !problems.is_empty() => eprintln!("{}", self::format(&problems)),
+        Format::Human => {},
+        Format::Json => println!("{}", json(&problems)),
+    }
+
+    summary(&problems)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use crate::parse::Origin;
+    use crate::report::{Problem, Severity, format, json};
+
+    #[test]
+    fn test_format() {
+        let problem = |severity, file: &str, message: &str| Problem { severity, code: None, file: PathBuf::from(file), message: message.to_owned(), origin: None, hint: None };
+
+        let problems = [
+            problem(Severity::Warning, "www/post.md", "Ambiguous page name"),
+            problem(Severity::Error, "www/about.html", "`<div>` is never closed"),
+            problem(Severity::Error, "www/post.md", "unexpected end of file"),
+        ];
+
+        assert_eq!(format(&problems), "www/about.html:\nerror: `<div>` is never closed\n\nwww/post.md:\nerror: unexpected end of file\nwarning: Ambiguous page name\n\n2 errors and 1 warning in 2 files");
+        assert_eq!(format(&problems[..1]), "www/post.md:\nwarning: Ambiguous page name\n\n0 errors and 1 warning in 1 file");
+    }
+
+    #[test]
+    fn test_json() {
+        let file = tempfile::Builder::new().suffix(".html").tempfile().unwrap();
+        let path = file.path().to_path_buf();
+        std::fs::write(&path, "<page>\n  <p.a#b>\"Hi\"</p>\n</page>\n").unwrap();
+
+        let problems = [Problem {
+            severity: Severity::Warning,
+            code: Some("duplicate-id"),
+            file: path.clone(),
+            message: "duplicate id `b`".to_owned(),
+            origin: Some(Origin { source: path.clone(), offset: 13, depth: 1, token_length: 2 }),
+            hint: None,
+        }];
+
+        let value = serde_json::from_str::<serde_json::Value>(&json(&problems)).unwrap();
+        assert_eq!(value[0]["severity"], "warning");
+        assert_eq!(value[0]["code"], "duplicate-id");
+        assert_eq!(value[0]["location"]["line"], 2);
+        assert_eq!(value[0]["location"]["column"], 7);
+        assert_eq!(value[0]["hint"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,54 @@
+use std::path::Path;
+use log::debug;
+use crate::error::*;
+
+/// The files of a new site, relative to its root
+const FILES: [(&str, &str); 3] = [
+    ("site.toml", include_str!("site/site.toml")),
+    ("www/index.en.html", include_str!("site/www/index.en.html")),
+    ("include/frame.html", include_str!("site/include/frame.html")),
+];
+
+/// Creates a new site in the given directory. Existing files are never overwritten, so the site must not exist yet.
+pub async fn scaffold(root: &Path) -> Result<()> {
+    for (file, _) in FILES {
+        if tokio::fs::try_exists(root.join(file)).await? {
+            return Err(Error::BuildError(BuildError::AlreadyExists(root.join(file))));
+        }
+    }
+
+    for (file, contents) in FILES {
+        let path = root.join(file);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        debug!("Creating {:?}", &path);
+        tokio::fs::write(&path, contents).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::error::*;
+    use crate::scaffold::scaffold;
+
+    #[test]
+    fn test_scaffold() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("site");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(scaffold(&root)).unwrap();
+
+        let config = toml::de::from_str::<Config>(&std::fs::read_to_string(root.join("site.toml")).unwrap()).unwrap();
+        assert_eq!(config.pages[0].name, "index");
+        assert!(root.join("www/index.en.html").is_file());
+        assert!(root.join("include/frame.html").is_file());
+
+        assert!(matches!(runtime.block_on(scaffold(&root)), Err(Error::BuildError(BuildError::AlreadyExists(_)))));
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use actix_files::NamedFile;
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use log::info;
+use tokio::sync::broadcast;
+use crate::{
+    build,
+    build::Dependencies,
+    config::Config,
+    error::*,
+    watch::watch,
+};
+
+/// The endpoint pages listen on for rebuilds
+const LIVE_RELOAD: &str = "/__livereload";
+
+/// Injected into every page served while live reload is enabled. It is never written to the build directory.
+const LIVE_RELOAD_CLIENT: &str = include_str!("livereload.js");
+
+/// What the browser should do after a rebuild
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reload {
+    /// Reload the page, as it may have been rebuilt
+    Page,
+    /// Only stylesheets changed, so they can be swapped without reloading
+    Styles,
+}
+
+impl Reload {
+    fn event(&self) -> &'static str {
+        match self {
+            Self::Page => "reload",
+            Self::Styles => "styles",
+        }
+    }
+
+    /// What the changes of a rebuild require, if anything
+    fn after(changed: &HashSet<PathBuf>, rebuilt: usize) -> Option<Self> {
+        if rebuilt > 0 {
+            return Some(Self::Page);
+        }
+
+        if !changed.is_empty() && changed.iter().all(|path| path.extension().is_some_and(|ext| ext == "css")) {
+            return Some(Self::Styles);
+        }
+
+        None
+    }
+}
+
+/// Everything requests are resolved against
+struct Site {
+    /// The site root, which the build directory is relative to
+    root: PathBuf,
+    /// The current config. It is read for every request, as it changes whenever `site.toml` is reloaded.
+    config: fn() -> Arc<Config>,
+    /// Notifies connected pages of rebuilds, if live reload is enabled
+    reload: Option<broadcast::Sender<Reload>>,
+}
+
+impl Site {
+    fn build(&self, config: &Config) -> PathBuf {
+        self.root.join(&config.build)
+    }
+
+    /// Finds the file a request path refers to. Paths without a language prefix are resolved against the default language,
+    /// and extensionless paths are resolved to their `.html` file, or `index.html` for directories.
+    /// The path is percent-decoded before anything else, so files whose names contain spaces or non-ASCII characters can be found.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let path = percent_encoding::percent_decode_str(path).decode_utf8().ok()?;
+        let path = sanitise(&path)?;
+        let config = (self.config)();
+
+        let prefixed = match path.components().next() {
+            Some(Component::Normal(first)) if is_language(&config, &first.to_string_lossy()) => vec![path],
+            _ => vec![PathBuf::from(&config.default_language).join(&path), path],
+        };
+
+        prefixed.into_iter()
+            .flat_map(|path| {
+                let html = match path.file_name() {
+                    Some(name) => path.with_file_name(format!("{}.html", name.to_string_lossy())),
+                    None => path.join("index.html"),
+                };
+
+                [path.clone(), html, path.join("index.html")]
+            })
+            .map(|path| self.build(&config).join(path))
+            .find(|path| path.is_file())
+    }
+
+    /// The 404 page of the language the path is prefixed with, or of the default language
+    fn not_found(&self, path: &str) -> Option<PathBuf> {
+        let config = (self.config)();
+        let language = path.trim_start_matches('/')
+            .split('/')
+            .next()
+            .filter(|segment| is_language(&config, segment))
+            .unwrap_or(&config.default_language);
+
+        [PathBuf::from(language).join("404.html"), PathBuf::from("404.html")].into_iter()
+            .map(|path| self.build(&config).join(path))
+            .find(|path| path.is_file())
+    }
+}
+
+fn is_language(config: &Config, segment: &str) -> bool {
+    config.languages.iter().any(|i| i.name == segment)
+}
+
+/// Turns a request path into a relative one, refusing any which would escape the build directory
+fn sanitise(path: &str) -> Option<PathBuf> {
+    let mut sanitised = PathBuf::new();
+
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(segment) => sanitised.push(segment),
+            Component::CurDir => {},
+            _ => return None,
+        }
+    }
+
+    Some(sanitised)
+}
+
+/// Places the live reload client before the closing body tag, or at the end of the document if there is none
+fn inject(mut html: String) -> String {
+    let script = format!("<script>{}</script>", LIVE_RELOAD_CLIENT);
+
+    match html.rfind("</body>") {
+        Some(index) => html.insert_str(index, &script),
+        None => html.push_str(&script),
+    }
+
+    html
+}
+
+async fn respond(req: &HttpRequest, site: &Site, file: PathBuf) -> actix_web::Result<HttpResponse> {
+    if site.reload.is_some() && file.extension().is_some_and(|ext| ext == "html") {
+        return Ok(HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+            .body(inject(tokio::fs::read_to_string(file).await?)));
+    }
+
+    Ok(NamedFile::open_async(file).await?.into_response(req))
+}
+
+async fn handle(req: HttpRequest, site: web::Data<Site>) -> actix_web::Result<HttpResponse> {
+    let path = req.path();
+
+    if let Some(file) = site.resolve(path) {
+        return respond(&req, &site, file).await;
+    }
+
+    let mut response = match site.not_found(path) {
+        Some(file) => respond(&req, &site, file).await?,
+        None => HttpResponse::NotFound().content_type("text/plain").body("Not Found"),
+    };
+
+    *response.status_mut() = StatusCode::NOT_FOUND;
+    Ok(response)
+}
+
+/// A stream of server-sent events, one for each rebuild
+async fn events(site: web::Data<Site>) -> HttpResponse {
+    let Some(reload) = &site.reload else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let events = futures_util::stream::unfold(reload.subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(reload) => {
+                    let event = format!("event: {}\ndata: {}\n\n", reload.event(), reload.event());
+                    return Some((Ok::<_, actix_web::Error>(web::Bytes::from(event)), rx));
+                },
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(events)
+}
+
+/// Serves the build directory until interrupted.
+/// Given the dependencies of the initial build, pages are rebuilt as their sources change, and open pages are reloaded once the rebuild finishes.
+pub async fn serve(root: PathBuf, host: String, port: u16, dependencies: Option<Dependencies>) -> Result<()> {
+    let (tx, _) = broadcast::channel(16);
+
+    let site = web::Data::new(Site {
+        root,
+        config: build::config,
+        reload: dependencies.is_some().then(|| tx.clone()),
+    });
+
+    info!("Serving {:?} on http://{}:{}", site.build(&build::config()), &host, port);
+
+    let server = HttpServer::new(move || App::new()
+        .app_data(site.clone())
+        .route(LIVE_RELOAD, web::get().to(events))
+        .default_service(web::to(handle)))
+        .bind((host.as_str(), port))?
+        .run();
+
+    let Some(dependencies) = dependencies else {
+        return Ok(server.await?);
+    };
+
+    let on_rebuild = move |changed: &HashSet<PathBuf>, rebuilt: usize| {
+        if let Some(reload) = Reload::after(changed, rebuilt) {
+            let _ = tx.send(reload);
+        }
+    };
+
+    tokio::select! {
+        result = server => Ok(result?),
+        result = watch(dependencies, on_rebuild) => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use crate::config::{Config, LanguageConfig};
+    use crate::serve::{Reload, Site, inject};
+
+    #[test]
+    fn test_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        for file in ["en/index.html", "en/about.html", "en/blog/index.html", "en/404.html", "en/über.html", "de/about.html", "style.css", "my file.css"] {
+            std::fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        fn language(name: &str) -> Arc<LanguageConfig> {
+            Arc::new(LanguageConfig { name: name.to_owned(), ..LanguageConfig::default() })
+        }
+
+        let site = Site {
+            reload: None,
+            root: root.clone(),
+            config: || Arc::new(Config {
+                default_language: "en".to_owned(),
+                languages: vec![language("en"), language("de")],
+                ..Config::default()
+            }),
+        };
+
+        let resolve = |path: &str| site.resolve(path).map(|path| path.strip_prefix(&root).unwrap().to_path_buf());
+
+        assert_eq!(resolve("/"), Some(PathBuf::from("en/index.html")));
+        assert_eq!(resolve("/about"), Some(PathBuf::from("en/about.html")));
+        assert_eq!(resolve("/de/about"), Some(PathBuf::from("de/about.html")));
+        assert_eq!(resolve("/en/blog/"), Some(PathBuf::from("en/blog/index.html")));
+        assert_eq!(resolve("/style.css"), Some(PathBuf::from("style.css")));
+        assert_eq!(resolve("/de/index"), None);
+        assert_eq!(resolve("/../etc/passwd"), None);
+        assert_eq!(resolve("/my%20file.css"), Some(PathBuf::from("my file.css")));
+        assert_eq!(resolve("/%C3%BCber"), Some(PathBuf::from("en/über.html")));
+        assert_eq!(resolve("/%2e%2e/etc/passwd"), None);
+        assert_eq!(site.not_found("/de/missing"), None);
+        assert_eq!(site.not_found("/missing"), Some(root.join("en/404.html")));
+    }
+
+    #[test]
+    fn test_live_reload() {
+        assert_eq!(inject("<html><body><p>Hi</p></body></html>".to_owned()), format!("<html><body><p>Hi</p><script>{}</script></body></html>", super::LIVE_RELOAD_CLIENT));
+        assert!(inject("<p>Fragment</p>".to_owned()).ends_with("</script>"));
+
+        let changed = |paths: &[&str]| paths.iter().map(PathBuf::from).collect();
+        assert_eq!(Reload::after(&changed(&["www/about.en.html"]), 1), Some(Reload::Page));
+        assert_eq!(Reload::after(&changed(&["www/style.css"]), 0), Some(Reload::Styles));
+        assert_eq!(Reload::after(&changed(&["www/.about.en.html.swp"]), 0), None);
+    }
+}
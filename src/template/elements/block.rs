@@ -2,22 +2,46 @@ use std::collections::HashMap;
 use crate::error::*;
 use crate::parse;
 use crate::parse::Attribute;
-use crate::parse::Element as ParseElement;
+use crate::template::RenderContext;
 use crate::template::elements::Body;
 use crate::template::elements::Element;
+use crate::template::elements::render_body;
 
+/// A named region of a template. Renders the override provided by the invoking page if there is one, or its own body otherwise.
 pub struct BlockElement {
     pub(super) source: parse::Element,
     pub(super) body: Vec<Body>,
     pub(super) attr: HashMap<String, Attribute>
 }
 
+/// The name of the block, if the element is one
+pub fn block_name(element: &parse::Element) -> Option<&str> {
+    if element.name != "block" {
+        return None;
+    }
+
+    element.attributes.iter()
+        .find(|i| i.name == "name")
+        .map(|i| i.value.as_str())
+}
+
 impl Element for BlockElement {
     fn name(&self) -> String {
         self.source.name.clone()
     }
 
-    fn render(&self, depth: u64) -> String {
-        String::new()
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        let name = self.attr.get("name")
+            .ok_or_else(|| Error::BuildError(BuildError::MissingAttribute {
+                tag: self.name(),
+                attribute: "name".to_owned(),
+                origin: self.source.origin.clone(),
+            }))?;
+
+        if let Some(html) = cx.blocks.last().and_then(|blocks| blocks.get(&name.value)) {
+            return Ok(format!("{}{}", cx.indent(depth), html));
+        }
+
+        render_body(&self.body, cx, depth)
     }
-}
\ No newline at end of file
+}
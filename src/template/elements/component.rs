@@ -1,13 +1,13 @@
 use std::collections::HashMap;
 use crate::error::*;
 use crate::parse;
-use crate::parse::Attribute;
-use crate::template::elements::{Body, Element};
+use crate::template::{RenderContext, Variable};
+use crate::template::registry::{Component, Registry};
+use crate::template::elements::{Body, Element, evaluate_attribute, from_body, from_element, render_body};
 
+/// A component definition. Definitions are collected into the registry ahead of time and only emit content when invoked.
 pub struct ComponentElement {
     pub(super) source: parse::Element,
-    pub(super) body: Vec<Body>,
-    pub(super) attr: HashMap<String, Attribute>
 }
 
 impl Element for ComponentElement {
@@ -15,7 +15,85 @@ impl Element for ComponentElement {
         self.source.name.clone()
     }
 
-    fn render(&self, depth: u64) -> String {
-        String::new()
+    fn render(&self, _cx: &mut RenderContext, _depth: u64) -> Result<String> {
+        Component::new(&self.source)?;
+
+        Ok(String::new())
     }
-}
\ No newline at end of file
+}
+
+/// An invocation of a component through a tag of the same name.
+/// Children with a `slot` attribute fill the slot of that name, while all others fill the default slot.
+pub struct InstanceElement {
+    pub(super) source: parse::Element,
+    pub(super) component: Component,
+    /// The content of each filled slot
+    pub(super) slots: HashMap<String, Vec<Body>>,
+}
+
+impl InstanceElement {
+    pub fn new(source: parse::Element, component: Component, registry: &Registry) -> Self {
+        let mut slots = HashMap::<String, Vec<Body>>::new();
+
+        for child in source.body.iter() {
+            let (slot, child) = match child {
+                parse::Body::Element(el) => match el.attributes.iter().find(|i| i.name == "slot") {
+                    Some(slot) => {
+                        let mut el = el.clone();
+                        el.attributes.retain(|i| i.name != "slot");
+                        (slot.value.clone(), Body::Element(from_element(&el, registry)))
+                    },
+                    None => ("default".to_owned(), Body::Element(from_element(el, registry)))
+                },
+                child => ("default".to_owned(), from_body(std::slice::from_ref(child), registry).remove(0))
+            };
+
+            slots.entry(slot).or_default().push(child);
+        }
+
+        Self { source, component, slots }
+    }
+}
+
+impl Element for InstanceElement {
+    fn name(&self) -> String {
+        self.source.name.clone()
+    }
+
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        let mut scope = HashMap::new();
+        for prop in self.component.props.iter() {
+            scope.insert(prop.name.clone(), Variable::Value(toml::Value::String(evaluate_attribute(prop, cx)?)));
+        }
+
+        for attr in self.source.attributes.iter() {
+            scope.insert(attr.name.clone(), Variable::Value(toml::Value::String(evaluate_attribute(attr, cx)?)));
+        }
+
+        // Slots are filled in the scope of the invoking page, not the component's
+        let mut slots = HashMap::new();
+        for (name, body) in self.slots.iter() {
+            slots.insert(name.clone(), render_body(body, cx, depth)?);
+        }
+
+        if cx.components.contains(&self.component.name) {
+            let mut chain = cx.components.clone();
+            chain.push(self.component.name.clone());
+
+            return Err(Error::BuildError(BuildError::ComponentCycle { chain, origin: self.source.origin.clone() }));
+        }
+
+        cx.dependencies.insert(self.component.source.origin.source.clone());
+        let body = from_body(&self.component.source.body, &cx.registry);
+
+        cx.components.push(self.component.name.clone());
+        cx.scope.push(scope);
+        cx.slots.push(slots);
+        let html = render_body(&body, cx, depth);
+        cx.slots.pop();
+        cx.scope.pop();
+        cx.components.pop();
+
+        html
+    }
+}
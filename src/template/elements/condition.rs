@@ -2,21 +2,64 @@ use std::collections::HashMap;
 use crate::error::*;
 use crate::parse;
 use crate::parse::Attribute;
-use crate::template::elements::Body;
-use crate::template::elements::Element;
+use crate::compile::truthy;
+use crate::template::RenderContext;
+use crate::template::elements::{Body, Element, render_body};
 
+/// Only renders its body if its condition holds.
+/// A condition is either an `if`, or continues the chain of the conditions immediately preceding it through `else-if` or `else`.
 pub struct ConditionElement {
     pub(super) source: parse::Element,
     pub(super) body: Vec<Body>,
     pub(super) attr: HashMap<String, Attribute>
 }
 
+impl ConditionElement {
+    /// Whether a previous branch of the chain has been taken, failing if there is no chain to continue
+    fn taken(&self, cx: &RenderContext, branch: &str) -> Result<bool> {
+        cx.branches.last()
+            .copied()
+            .flatten()
+            .ok_or_else(|| Error::BuildError(BuildError::DanglingBranch {
+                branch: branch.to_owned(),
+                origin: self.source.origin.clone(),
+            }))
+    }
+}
+
 impl Element for ConditionElement {
     fn name(&self) -> String {
         self.source.name.clone()
     }
 
-    fn render(&self, depth: u64) -> String {
-        String::new()
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        // Which branch to render, and the state of the chain afterwards. An `else` closes the chain.
+        let (matches, chain) = if let Some(condition) = self.attr.get("if") {
+            let matches = truthy(&condition.value, &condition.origin, cx)?;
+            (matches, Some(matches))
+        } else if let Some(condition) = self.attr.get("else-if") {
+            let matches = !self.taken(cx, "else-if")? && truthy(&condition.value, &condition.origin, cx)?;
+            (matches, Some(matches || self.taken(cx, "else-if")?))
+        } else if self.attr.contains_key("else") {
+            (!self.taken(cx, "else")?, None)
+        } else {
+            return Err(Error::BuildError(BuildError::MissingAttribute {
+                tag: self.name(),
+                attribute: "if".to_owned(),
+                origin: self.source.origin.clone(),
+            }));
+        };
+
+        let html = if matches {
+            render_body(&self.body, cx, depth)?
+        } else {
+            String::new()
+        };
+
+        if let Some(branch) = cx.branches.last_mut() {
+            *branch = chain;
+        }
+
+        Ok(html)
     }
-}
\ No newline at end of file
+}
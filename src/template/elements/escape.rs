@@ -2,8 +2,11 @@ use std::collections::HashMap;
 use crate::error::*;
 use crate::parse;
 use crate::parse::Attribute;
+use crate::template::{RenderContext, escape_text};
 use crate::template::elements::{Body, Element};
 
+/// Emits its body verbatim. The parser leaves the body uninterpreted, so it consists of a single literal.
+/// In `html` mode (the default) the text is entity-escaped, while `raw` mode passes it through as markup.
 pub struct EscapeElement {
     pub(super) source: parse::Element,
     pub(super) body: Vec<Body>,
@@ -15,7 +18,24 @@ impl Element for EscapeElement {
         self.source.name.clone()
     }
 
-    fn render(&self, depth: u64) -> String {
-        String::new()
+    fn render(&self, _cx: &mut RenderContext, _depth: u64) -> Result<String> {
+        let text = self.body.iter()
+            .filter_map(|i| match i {
+                Body::Literal(lit) => Some(String::from_utf8_lossy(&lit.body).into_owned()),
+                _ => None
+            })
+            .collect::<String>();
+
+        match self.attr.get("mode") {
+            None => Ok(escape_text(text)),
+            Some(mode) if mode.value == "html" => Ok(escape_text(text)),
+            Some(mode) if mode.value == "raw" => Ok(text),
+            Some(mode) => Err(Error::BuildError(BuildError::InvalidAttribute {
+                tag: self.name(),
+                attribute: "mode".to_owned(),
+                value: mode.value.clone(),
+                origin: mode.origin.clone(),
+            }))
+        }
     }
 }
@@ -0,0 +1,22 @@
+use crate::parse;
+use crate::error::*;
+use crate::template::RenderContext;
+use crate::template::elements::{Body, Element, render_body};
+
+/// The root of every parsed file. It has no markup of its own, so only its children are emitted.
+pub struct FragmentElement {
+    pub(super) source: parse::Element,
+    pub(super) body: Vec<Body>,
+}
+
+impl Element for FragmentElement {
+    fn name(&self) -> String {
+        self.source.name.clone()
+    }
+
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        let html = render_body(&self.body, cx, depth)?;
+
+        Ok(html.trim_start().to_owned())
+    }
+}
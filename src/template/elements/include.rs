@@ -2,11 +2,13 @@ use std::collections::HashMap;
 use crate::error::*;
 use crate::parse;
 use crate::parse::Attribute;
-use crate::template::elements::{Body, Element};
+use crate::parse::ParsingContext;
+use crate::template::{RenderContext, Variable, resolve_path};
+use crate::template::elements::{Element, evaluate_attribute, from_element};
 
+/// Splices another file into the current one. All attributes except `src` become variables within the included file.
 pub struct IncludeElement {
     pub(super) source: parse::Element,
-    pub(super) body: Vec<Body>,
     pub(super) attr: HashMap<String, Attribute>
 }
 
@@ -15,7 +17,40 @@ impl Element for IncludeElement {
         self.source.name.clone()
     }
 
-    fn render(&self, depth: u64) -> String {
-        String::new()
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        let src = self.attr.get("src")
+            .ok_or_else(|| Error::BuildError(BuildError::MissingAttribute {
+                tag: self.name(),
+                attribute: "src".to_owned(),
+                origin: self.source.origin.clone(),
+            }))?;
+
+        let path = resolve_path(evaluate_attribute(src, cx)?, self.source.origin.source.clone());
+        let path = path.canonicalize().unwrap_or(path);
+
+        if cx.includes.contains(&path) {
+            let mut chain = cx.includes.clone();
+            chain.push(path);
+
+            return Err(Error::BuildError(BuildError::IncludeCycle(chain)));
+        }
+
+        cx.dependencies.insert(path.clone());
+
+        let mut scope = HashMap::new();
+        for attr in self.source.attributes.iter().filter(|i| i.name != "src") {
+            scope.insert(attr.name.clone(), Variable::Value(toml::Value::String(evaluate_attribute(attr, cx)?)));
+        }
+
+        let source = std::fs::read_to_string(&path)?;
+        let fragment = ParsingContext::new(source, path.clone())?.parse()?;
+
+        cx.includes.push(path);
+        cx.scope.push(scope);
+        let html = from_element(&fragment, &cx.registry).render(cx, depth);
+        cx.scope.pop();
+        cx.includes.pop();
+
+        Ok(format!("{}{}", cx.indent(depth), html?))
     }
-}
\ No newline at end of file
+}
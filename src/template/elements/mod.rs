@@ -4,19 +4,33 @@ pub mod escape;
 pub mod include;
 pub mod template;
 pub mod condition;
+pub mod fragment;
+pub mod page;
+pub mod slot;
 
 use std::collections::HashMap;
 use crate::parse;
 use crate::parse::Attribute;
 use crate::parse::Expression;
 use crate::parse::Literal;
+use crate::parse::VOID_ELEMENTS;
 use crate::parse::Element as ParseElement;
 use crate::template::elements::block::BlockElement;
-use crate::template::elements::component::ComponentElement;
+use crate::template::elements::component::{ComponentElement, InstanceElement};
 use crate::template::elements::condition::ConditionElement;
 use crate::template::elements::escape::EscapeElement;
 use crate::template::elements::include::IncludeElement;
 use crate::template::elements::template::TemplateElement;
+use crate::template::elements::fragment::FragmentElement;
+use crate::template::elements::page::PageElement;
+use crate::template::elements::slot::SlotElement;
+use crate::template::registry::Registry;
+use crate::template::escape_attribute;
+use crate::template::escape_text;
+use crate::template::RenderContext;
+use crate::compile::evaluate;
+use crate::compile::Output;
+use crate::error::*;
 
 pub enum Body {
     Element(Box<dyn Element>),
@@ -24,34 +38,109 @@ pub enum Body {
     Script(Expression)
 }
 
-pub fn from_element(element: &ParseElement) -> Box<dyn Element> {
+impl Body {
+    pub fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        Ok(match self {
+            Self::Element(el) => el.render(cx, depth)?,
+            Self::Literal(lit) => format!("{}{}", cx.indent(depth), escape_text(String::from_utf8_lossy(&lit.body))),
+            Self::Script(expr) => match evaluate(&expr.body, &expr.origin, cx)? {
+                Output::Text(text) => format!("{}{}", cx.indent(depth), escape_text(text)),
+                Output::Markup(markup) => format!("{}{}", cx.indent(depth), markup),
+            },
+        })
+    }
+}
+
+/// The value of an attribute, evaluating it first if it was given as an expression
+pub fn evaluate_attribute(attr: &Attribute, cx: &RenderContext) -> Result<String> {
+    if !attr.is_expression {
+        return Ok(attr.value.clone());
+    }
+
+    Ok(match evaluate(&attr.value, &attr.origin, cx)? {
+        Output::Text(text) => text,
+        Output::Markup(markup) => markup,
+    })
+}
+
+/// The value of an attribute as it is written between double quotes.
+/// Literal values are markup like any other text of the page, so only their quotes are escaped, while the results of expressions are escaped entirely.
+fn attribute_html(attr: &Attribute, cx: &RenderContext) -> Result<String> {
+    if attr.is_expression {
+        return Ok(escape_attribute(evaluate_attribute(attr, cx)?));
+    }
+
+    Ok(attr.value.replace('"', "&quot;").replace('\'', "&#39;"))
+}
+
+/// Renders each child in turn at the given depth.
+/// Consecutive `<condition>` elements form a chain, which any other node ends.
+pub fn render_body(body: &[Body], cx: &mut RenderContext, depth: u64) -> Result<String> {
+    let mut html = String::new();
+
+    cx.branches.push(None);
+
+    for child in body.iter() {
+        match child.render(cx, depth) {
+            Ok(child) => html.push_str(&child),
+            Err(err) => {
+                cx.branches.pop();
+                return Err(err);
+            }
+        }
+
+        if !matches!(child, Body::Element(el) if el.name() == "condition") {
+            *cx.branches.last_mut().unwrap() = None;
+        }
+    }
+
+    cx.branches.pop();
+
+    Ok(html)
+}
+
+/// Converts a parsed element into its renderable form.
+/// Tags without behaviour of their own are looked up in the registry's components before being treated as plain HTML.
+pub fn from_element(element: &ParseElement, registry: &Registry) -> Box<dyn Element> {
     let attr = element
         .attributes
         .iter()
         .map(|attr| (attr.name.clone(), attr.clone()))
         .collect();
-    let body = element.body.iter().map(|i| match i {
-        parse::Body::Element(el) => Body::Element(from_element(el)),
-        parse::Body::Literal(lit) => Body::Literal(lit.clone()),
-        parse::Body::Expression(expr) => Body::Script(expr.clone()),
-    }).collect::<Vec<_>>();
+    // Components can't share their name with a built-in element, so there is no ambiguity in checking them first
+    if let Some(component) = registry.components.get(&element.name.to_lowercase()) {
+        return Box::new(InstanceElement::new(element.clone(), component.clone(), registry));
+    }
+
+    let body = from_body(&element.body, registry);
     let source = element.clone();
 
-    return match element.name.as_str() {
+    match element.name.as_str() {
         "block" => Box::new(BlockElement { attr, body, source }),
-        "template" => Box::new(TemplateElement { attr, body, source }),
-        "include" => Box::new(IncludeElement { attr, body, source }),
-        "component" => Box::new(ComponentElement { attr, body, source }),
+        "template" => Box::new(TemplateElement { source }),
+        "include" => Box::new(IncludeElement { attr, source }),
+        "component" => Box::new(ComponentElement { source }),
         "escape" => Box::new(EscapeElement { attr, body, source }),
         "condition" => Box::new(ConditionElement { attr, body, source }),
+        "fragment" => Box::new(FragmentElement { body, source }),
+        "page" => Box::new(PageElement { attr, body, source }),
+        "slot" => Box::new(SlotElement { attr, body, source }),
         _ => Box::new(GenericElement { attr, body, source }),
-    };
+    }
+}
+
+pub fn from_body(body: &[parse::Body], registry: &Registry) -> Vec<Body> {
+    body.iter().map(|i| match i {
+        parse::Body::Element(el) => Body::Element(from_element(el, registry)),
+        parse::Body::Literal(lit) => Body::Literal(lit.clone()),
+        parse::Body::Expression(expr) => Body::Script(expr.clone()),
+    }).collect()
 }
 
 pub trait Element {
     fn name(&self) -> String;
 
-    fn render(&self, depth: u64) -> String;
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String>;
 }
 
 pub struct GenericElement {
@@ -65,7 +154,225 @@ impl Element for GenericElement {
         self.source.name.clone()
     }
 
-    fn render(&self, depth: u64) -> String {
-        String::new()
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        let name = self.name();
+        let mut html = format!("{}<{}", cx.indent(depth), &name);
+
+        // Attributes are emitted in source order. Classes given both through the selector shorthand and the `class` attribute are merged.
+        let mut emitted = Vec::with_capacity(self.source.attributes.len());
+        for attr in self.source.attributes.iter() {
+            if emitted.contains(&attr.name.as_str()) {
+                continue;
+            }
+
+            // Attributes written without a value are emitted without one, while empty values are kept
+            let value = if attr.name == "class" {
+                Some(self.source.attributes.iter()
+                    .filter(|i| i.name == "class")
+                    .map(|i| attribute_html(i, cx).map(|class| class.trim().to_owned()))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(" "))
+            } else {
+                match self.attr.get(&attr.name) {
+                    Some(attr) if !attr.is_boolean => Some(attribute_html(attr, cx)?),
+                    _ => None,
+                }
+            };
+
+            match value {
+                Some(value) => html.push_str(&format!(" {}=\"{}\"", &attr.name, value)),
+                None => html.push_str(&format!(" {}", &attr.name)),
+            }
+
+            emitted.push(attr.name.as_str());
+        }
+
+        html.push('>');
+
+        if VOID_ELEMENTS.contains(&name.as_str()) {
+            return Ok(html);
+        }
+
+        html.push_str(&render_body(&self.body, cx, depth + 1)?);
+
+        if !self.body.is_empty() {
+            html.push_str(&cx.indent(depth));
+        }
+
+        html.push_str(&format!("</{}>", &name));
+
+        Ok(html)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use crate::{BuildError, Error, SITE_ROOT};
+    use crate::parse::ParsingContext;
+    use crate::template::elements::from_element;
+    use crate::template::registry::Registry;
+    use crate::template::RenderContext;
+
+    fn render_result_with(source: &str, definitions: &str) -> crate::Result<String> {
+        let mut registry = Registry::default();
+        registry.register(&ParsingContext::new(definitions.to_owned(), "include.html").unwrap().parse().unwrap()).unwrap();
+
+        let mut cx = RenderContext { registry: Arc::new(registry), ..RenderContext::default() };
+        let mut parser = ParsingContext::new(source.to_owned(), "test.html").unwrap();
+        from_element(&parser.parse().unwrap(), &cx.registry).render(&mut cx, 0)
+    }
+
+    fn render_with(source: &str, definitions: &str) -> String {
+        render_result_with(source, definitions).unwrap()
+    }
+
+    fn render(source: &str) -> String {
+        render_with(source, "")
+    }
+
+    fn render_result(source: &str) -> crate::Result<String> {
+        render_result_with(source, "")
+    }
+
+    #[test]
+    fn test_render_generic() {
+        assert_eq!(render(r#"<div.a.b#main title="Tom &amp; 'Jerry'"><p>"1 < 2"</p></div>"#), r#"<div title="Tom &amp; &#39;Jerry&#39;" class="a b" id="main"><p>1 &lt; 2</p></div>"#);
+        assert_eq!(render(r#"<p.a#b.c class="d">"text"</p>"#), r#"<p class="d a c" id="b">text</p>"#);
+        assert_eq!(render(r#"<img alt="" src="a.png" title={"<a & b>"}/><input disabled value={""}/>"#), r#"<img alt="" src="a.png" title="&lt;a &amp; b&gt;"><input disabled value="">"#);
+    }
+
+    #[test]
+    fn test_render_text() {
+        assert_eq!(render("<p>Tom &amp; Jerry <b>\"bold\"</b> text</p>\n<p>\n    \"Hi\"\n</p>"), "<p>Tom &amp; Jerry <b>bold</b> text</p><p>Hi</p>");
+        assert_eq!(render("<!DOCTYPE html><pre>  a\n\n b</pre><script>if (a < b) {}</script>"), "<!DOCTYPE html><pre>  a\n\n b</pre><script>if (a < b) {}</script>");
+
+        // Whitespace between inline elements shows, so it is kept as a single space
+        assert_eq!(render("<p><b>a</b> <i>b</i>\n\t<a href=\"/\">c</a></p>\n<ul>\n  <li>a</li>\n  <li>b</li>\n</ul>"), "<p><b>a</b> <i>b</i> <a href=\"/\">c</a></p><ul><li>a</li><li>b</li></ul>");
+        assert_eq!(render("<p>\n  <condition if=\"false\">a</condition>\n  <condition else>b</condition>\n</p>"), "<p>b</p>");
+
+        // Apostrophes and quotes in prose are text, unless they enclose a literal within the same run of text
+        assert_eq!(render("<p>Rock of the '90s and '80s</p>"), "<p>Rock of the '90s and '80s</p>");
+        assert_eq!(render("<p>'Hi <b>x</b>'</p><p>It's `code`</p>"), "<p>'Hi <b>x</b>'</p><p>It's `code`</p>");
+        assert_eq!(render("<p>'a &amp; b', \"c < d\" and ´e´</p>"), "<p>a &amp;amp; b, c &lt; d and e</p>");
+    }
+
+    #[test]
+    fn test_render_void() {
+        assert_eq!(render(r#"<div><br/><img src="a.png"/><span/></div>"#), r#"<div><br><img src="a.png"><span></span></div>"#);
+    }
+
+    #[test]
+    fn test_render_template() {
+        let definitions = r#"
+            <template name="frame" bind="content"><main><h1>{title}</h1>{content}</main></template>
+            <template name="plain" bind="content">{content}</template>
+        "#;
+
+        assert_eq!(render_with(r#"<page template="frame" title="About"><p>"Hi"</p></page>"#, definitions), "<main><h1>About</h1><p>Hi</p></main>");
+        assert_eq!(render_with(r#"<page template={"plain"}><p>"Hi"</p></page>"#, definitions), "<p>Hi</p>");
+    }
+
+    #[test]
+    fn test_render_include() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("include")).unwrap();
+
+        // The site root is global, so this is the only test which may set it
+        std::fs::write(dir.join("site.toml"), "").unwrap();
+        SITE_ROOT.get_or_init(|| Arc::new(dir.join("site.toml")));
+
+        std::fs::write(dir.join("include/inner.html"), r#"<p>{`Hello ${name}`}</p>"#).unwrap();
+        std::fs::write(dir.join("a.html"), r#"<include src="b.html"/>"#).unwrap();
+        std::fs::write(dir.join("b.html"), r#"<include src="a.html"/>"#).unwrap();
+
+        let render_file = |file: &str, source: &str| {
+            let mut parser = ParsingContext::new(source.to_owned(), dir.join(file)).unwrap();
+            from_element(&parser.parse().unwrap(), &Registry::default()).render(&mut RenderContext::default(), 0)
+        };
+
+        assert_eq!(render_file("page.html", r##"<div><include src="#include/inner.html" name="World"/></div>"##).unwrap(), "<div><p>Hello World</p></div>");
+        assert_eq!(render_file("page.html", r#"<div><include src="include/inner.html" name="You"/></div>"#).unwrap(), "<div><p>Hello You</p></div>");
+
+        match render_file("page.html", r#"<include src="a.html"/>"#) {
+            Err(Error::BuildError(BuildError::IncludeCycle(chain))) => {
+                let dir = dir.canonicalize().unwrap();
+                assert_eq!(chain, vec![dir.join("a.html"), dir.join("b.html"), dir.join("a.html")]);
+            }
+            _ => panic!("Expected an include cycle"),
+        }
+    }
+
+    #[test]
+    fn test_render_block() {
+        let definitions = r#"
+            <template name="frame" bind="content">
+                <head><block name="head"><title>"Default"</title></block></head>
+                <body><block name="sidebar"><aside>"Nothing"</aside></block>{content}</body>
+            </template>
+        "#;
+
+        assert_eq!(render_with(r#"<page template="frame"><block name="head"><title>{"Custom"}</title><link href="a.css"/></block><p>"Hi"</p></page>"#, definitions),
+                   r#"<head><title>Custom</title><link href="a.css"></head><body><aside>Nothing</aside><p>Hi</p></body>"#);
+        assert_eq!(render(r#"<block name="head"><p>"Default"</p></block>"#), "<p>Default</p>");
+    }
+
+    #[test]
+    fn test_render_component() {
+        let definitions = r#"
+            <component name="card" title="Untitled" variant="primary">
+                <div class={`card ${variant}`}><h2>{title}</h2><slot/><footer><slot name="footer">"No footer"</slot></footer></div>
+            </component>
+        "#;
+
+        assert_eq!(render_with(r#"<card title="Hello"><p>"Body"</p><a slot="footer" href="/">"Home"</a></card>"#, definitions),
+                   r#"<div class="card primary"><h2>Hello</h2><p>Body</p><footer><a href="/">Home</a></footer></div>"#);
+        assert_eq!(render_with(r#"<card variant="secondary"/>"#, definitions),
+                   r#"<div class="card secondary"><h2>Untitled</h2><footer>No footer</footer></div>"#);
+
+        // Props named after keywords are reached through `vars`
+        let field = r#"<component name="field" for="name"><label for={vars["for"]}><slot/></label></component>"#;
+        assert_eq!(render_with(r#"<field for="email">"Email"</field>"#, field), r#"<label for="email">Email</label>"#);
+
+        let cycles = r#"
+            <component name="nested"><div><nested/></div></component>
+            <component name="ping"><pong/></component>
+            <component name="pong"><ping/></component>
+        "#;
+
+        for (source, expected) in [("<nested/>", vec!["nested", "nested"]), ("<PING/>", vec!["ping", "pong", "ping"])] {
+            match render_result_with(source, cycles) {
+                Err(Error::BuildError(BuildError::ComponentCycle { chain, .. })) => assert_eq!(chain, expected),
+                _ => panic!("Expected a component cycle"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_condition() {
+        let chain = |value: i64| render(&format!(r#"
+            <condition if={{{value} == 1}}><p>"one"</p></condition>
+            <condition else-if={{{value} == 2}}><p>"two"</p></condition>
+            <condition else><p>"many"</p></condition>
+        "#));
+
+        assert_eq!(chain(1), "<p>one</p>");
+        assert_eq!(chain(2), "<p>two</p>");
+        assert_eq!(chain(3), "<p>many</p>");
+
+        assert_eq!(render(r#"<condition if={false}>"a"</condition><condition if={true}>"b"</condition>"#), "b");
+        assert!(matches!(render_result(r#"<p/><condition else>"a"</condition>"#), Err(Error::BuildError(BuildError::DanglingBranch { .. }))));
+    }
+
+    #[test]
+    fn test_render_escape() {
+        assert_eq!(render(r#"<pre><escape>fn main() { println!("<b>"); }</escape></pre>"#), r#"<pre>fn main() { println!("&lt;b&gt;"); }</pre>"#);
+        assert_eq!(render(r#"<escape mode="raw"><b>{bold}</b></escape><p>"after"</p>"#), r#"<b>{bold}</b><p>after</p>"#);
+    }
+
+    #[test]
+    fn test_render_expression() {
+        assert_eq!(render(r#"<p title={1 + 1}>{"<b>" + "bold</b>"}</p>"#), r#"<p title="2">&lt;b&gt;bold&lt;/b&gt;</p>"#);
     }
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use crate::error::*;
+use crate::parse;
+use crate::parse::Attribute;
+use crate::template::{RenderContext, Variable};
+use crate::template::elements::{Body, Element, evaluate_attribute, from_body, from_element, render_body};
+use crate::template::elements::block::block_name;
+
+/// The content of a page. If a `template` is named, the body is rendered into it, bound to the template's `bind` variable.
+/// All other attributes become variables available to the template, while `<block>` children override the template's block of the same name.
+pub struct PageElement {
+    pub(super) source: parse::Element,
+    pub(super) body: Vec<Body>,
+    pub(super) attr: HashMap<String, Attribute>
+}
+
+impl Element for PageElement {
+    fn name(&self) -> String {
+        self.source.name.clone()
+    }
+
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        let Some(template) = self.attr.get("template") else {
+            return render_body(&self.body, cx, depth);
+        };
+
+        let name = evaluate_attribute(template, cx)?;
+        let template = cx.registry.templates.get(&name)
+            .cloned()
+            .ok_or_else(|| Error::BuildError(BuildError::NoSuchTemplate {
+                name,
+                origin: template.origin.clone(),
+            }))?;
+
+        cx.dependencies.insert(template.source.origin.source.clone());
+
+        let mut scope = HashMap::new();
+        for attr in self.source.attributes.iter().filter(|i| i.name != "template") {
+            let value = evaluate_attribute(attr, cx)?;
+
+            if attr.name == "title" {
+                cx.page.insert("title".to_owned(), toml::Value::String(value.clone()));
+            }
+
+            scope.insert(attr.name.clone(), Variable::Value(toml::Value::String(value)));
+        }
+
+        // Overrides are rendered in the page's scope, before the template's variables are bound
+        let mut blocks = HashMap::new();
+        let mut body = Vec::with_capacity(self.source.body.len());
+        for child in self.source.body.iter() {
+            match child {
+                parse::Body::Element(el) if block_name(el).is_some() => {
+                    let html = from_element(el, &cx.registry).render(cx, depth)?;
+                    blocks.insert(block_name(el).unwrap().to_owned(), html.trim_start().to_owned());
+                },
+                child => body.push(child.clone())
+            }
+        }
+
+        scope.insert(template.bind.clone(), Variable::Markup(render_body(&from_body(&body, &cx.registry), cx, depth)?.trim_start().to_owned()));
+
+        cx.scope.push(scope);
+        cx.blocks.push(blocks);
+        let html = render_body(&from_body(&template.source.body, &cx.registry), cx, depth);
+        cx.blocks.pop();
+        cx.scope.pop();
+
+        html
+    }
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+use crate::error::*;
+use crate::parse;
+use crate::parse::Attribute;
+use crate::template::RenderContext;
+use crate::template::elements::{Body, Element, render_body};
+
+/// A placeholder within a component, replaced by the content the invocation provides for it.
+/// Without a `name`, it stands for the default slot. Its own body is the fallback if no content is provided.
+pub struct SlotElement {
+    pub(super) source: parse::Element,
+    pub(super) body: Vec<Body>,
+    pub(super) attr: HashMap<String, Attribute>
+}
+
+impl Element for SlotElement {
+    fn name(&self) -> String {
+        self.source.name.clone()
+    }
+
+    fn render(&self, cx: &mut RenderContext, depth: u64) -> Result<String> {
+        let name = self.attr.get("name").map(|i| i.value.as_str()).unwrap_or("default");
+
+        if let Some(fill) = cx.slots.last().and_then(|slots| slots.get(name)) {
+            return Ok(fill.clone());
+        }
+
+        render_body(&self.body, cx, depth)
+    }
+}
@@ -1,13 +1,12 @@
-use std::collections::HashMap;
 use crate::error::*;
 use crate::parse;
-use crate::parse::Attribute;
-use crate::template::elements::{Body, Element};
+use crate::template::RenderContext;
+use crate::template::registry::Template;
+use crate::template::elements::Element;
 
+/// A template definition. Definitions are collected into the registry ahead of time and only emit content when a page invokes them.
 pub struct TemplateElement {
     pub(super) source: parse::Element,
-    pub(super) body: Vec<Body>,
-    pub(super) attr: HashMap<String, Attribute>
 }
 
 impl Element for TemplateElement {
@@ -15,7 +14,9 @@ impl Element for TemplateElement {
         self.source.name.clone()
     }
 
-    fn render(&self, depth: u64) -> String {
-        String::new()
+    fn render(&self, _cx: &mut RenderContext, _depth: u64) -> Result<String> {
+        Template::new(&self.source)?;
+
+        Ok(String::new())
     }
 }
\ No newline at end of file
@@ -1,13 +1,129 @@
 pub mod elements;
+pub mod registry;
 
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::sync::Arc;
 use crate::{
-    SITE_ROOT
+    SITE_ROOT,
+    build::PageResolver,
+    config::Config,
+    config::LanguageConfig,
+    template::registry::Registry,
 };
 
-fn resolve_path<Path: AsRef<str>>(path: Path, current_file: PathBuf) -> PathBuf {
-    let site_root = SITE_ROOT.get()
-        .expect("Failed to acquire site root")
+/// A value which can be referenced from expressions
+#[derive(Debug, Clone)]
+pub enum Variable {
+    Value(toml::Value),
+    /// Rendered markup, such as the body bound to a template
+    Markup(String),
+}
+
+/// State available while rendering a page
+#[derive(Default)]
+pub struct RenderContext {
+    /// Information about the page being rendered, exposed to expressions as `page`
+    pub page: toml::Table,
+    pub language: Arc<LanguageConfig>,
+    pub config: Arc<Config>,
+    pub registry: Arc<Registry>,
+    /// Variables in scope, innermost last
+    pub scope: Vec<HashMap<String, Variable>>,
+    /// The chain of files currently being rendered, starting with the page itself
+    pub includes: Vec<PathBuf>,
+    /// The chain of components currently being rendered, outermost first
+    pub components: Vec<String>,
+    /// For each body being rendered, whether a branch of the current `<condition>` chain has already been taken, or `None` outside of a chain
+    pub branches: Vec<Option<bool>>,
+    /// For each component being rendered, the markup its invocation provided for each slot
+    pub slots: Vec<HashMap<String, String>>,
+    /// For each template being rendered, the markup the invoking page overrides each block with
+    pub blocks: Vec<HashMap<String, String>>,
+    /// Every file the page was built from, so it can be rebuilt when any of them change
+    pub dependencies: HashSet<PathBuf>,
+    /// The config as expressions see it, converted the first time an expression needs it
+    pub config_value: OnceCell<rune::Value>,
+}
+
+impl RenderContext {
+    pub fn new(page: &PageResolver, config: Arc<Config>, registry: Arc<Registry>) -> Self {
+        Self {
+            page: page.info(),
+            language: page.language.clone(),
+            config,
+            registry,
+            scope: vec![],
+            includes: vec![page.path.clone()],
+            components: vec![],
+            branches: vec![],
+            slots: vec![],
+            blocks: vec![],
+            dependencies: HashSet::from([page.path.clone()]),
+            config_value: OnceCell::new(),
+        }
+    }
+
+    /// Exposes a page's front matter as variables. A `title` also becomes the page's title.
+    pub fn front_matter(&mut self, front_matter: toml::Table) {
+        if let Some(title) = front_matter.get("title").filter(|i| i.is_str()) {
+            self.page.insert("title".to_owned(), title.clone());
+        }
+
+        self.scope.push(front_matter.into_iter()
+            .map(|(name, value)| (name, Variable::Value(value)))
+            .collect());
+    }
+
+    /// All variables currently in scope. Inner scopes shadow outer ones.
+    pub fn variables(&self) -> HashMap<&str, &Variable> {
+        self.scope.iter()
+            .flat_map(|scope| scope.iter())
+            .map(|(name, variable)| (name.as_str(), variable))
+            .collect()
+    }
+
+    /// The whitespace preceding a node at the given depth, or nothing if pretty-printing is disabled.
+    pub fn indent(&self, depth: u64) -> String {
+        if self.config.pretty {
+            format!("\n{}", "    ".repeat(depth as usize))
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Escapes text content so it can't be interpreted as markup
+pub fn escape_text<Text: AsRef<str>>(text: Text) -> String {
+    let mut escaped = String::with_capacity(text.as_ref().len());
+
+    for char in text.as_ref().chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            char => escaped.push(char),
+        }
+    }
+
+    escaped
+}
+
+/// Escapes an attribute value so it can be placed between either kind of quote
+pub fn escape_attribute<Text: AsRef<str>>(text: Text) -> String {
+    escape_text(text)
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+pub(crate) fn resolve_path<Path: AsRef<str>>(path: Path, current_file: PathBuf) -> PathBuf {
+    resolve_path_from(SITE_ROOT.get().expect("Failed to acquire site root"), path, current_file)
+}
+
+/// Resolves `path` as written in `current_file`: paths starting with `#` are relative to the directory containing `site_root`, the site config, and other relative paths to the directory containing the current file
+fn resolve_path_from<Path: AsRef<str>>(site_root: &std::path::Path, path: Path, current_file: PathBuf) -> PathBuf {
+    let site_root = site_root
         .parent()
         .expect("Invalid Site root")
         .to_path_buf();
@@ -22,24 +138,26 @@ fn resolve_path<Path: AsRef<str>>(path: Path, current_file: PathBuf) -> PathBuf
     let mut path = PathBuf::from(path);
 
     if path.is_relative() {
-        path = current_file.join(path);
+        path = current_file.parent()
+            .map(|dir| dir.join(&path))
+            .unwrap_or(path);
     }
 
-    return path;
+    path
 }
 
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
-    use crate::template::resolve_path;
+    use crate::template::resolve_path_from;
 
     #[test]
     fn test_resolve() {
         let site_root = PathBuf::from("/home/jcake/Code/personal-website/site.toml");
         let current_file = PathBuf::from("/home/jcake/Code/personal-website/www/home.en.html");
 
-        assert_eq!(resolve_path("#include/frame.html", current_file.clone()), PathBuf::from("/home/jcake/Code/personal-website/include/frame.html"));
-        assert_eq!(resolve_path("./frame.html", current_file.clone()), PathBuf::from("/home/jcake/Code/personal-website/www/frame.html"));
-        assert_eq!(resolve_path("frame.html", current_file.clone()), PathBuf::from("/home/jcake/Code/personal-website/www/frame.html"));
+        assert_eq!(resolve_path_from(&site_root, "#include/frame.html", current_file.clone()), PathBuf::from("/home/jcake/Code/personal-website/include/frame.html"));
+        assert_eq!(resolve_path_from(&site_root, "./frame.html", current_file.clone()), PathBuf::from("/home/jcake/Code/personal-website/www/frame.html"));
+        assert_eq!(resolve_path_from(&site_root, "frame.html", current_file.clone()), PathBuf::from("/home/jcake/Code/personal-website/www/frame.html"));
     }
 }
\ No newline at end of file
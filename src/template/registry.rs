@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::path::Path;
+use log::debug;
+use crate::{
+    error::*,
+    parse,
+    parse::ParsingContext,
+};
+
+/// A template definition found while scanning the template directories
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    /// The variable the invoking page's body is bound to
+    pub bind: String,
+    pub source: parse::Element,
+}
+
+impl Template {
+    pub fn new(source: &parse::Element) -> Result<Self> {
+        let attribute = |attribute: &str| source.attributes.iter()
+            .find(|i| i.name == attribute)
+            .map(|i| i.value.clone())
+            .ok_or_else(|| Error::BuildError(BuildError::MissingAttribute {
+                tag: source.name.clone(),
+                attribute: attribute.to_owned(),
+                origin: source.origin.clone(),
+            }));
+
+        Ok(Self {
+            name: attribute("name")?,
+            bind: attribute("bind")?,
+            source: source.clone(),
+        })
+    }
+}
+
+/// A component definition. Every attribute besides `name` declares a prop, whose value is its default.
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub name: String,
+    pub props: Vec<parse::Attribute>,
+    pub source: parse::Element,
+}
+
+impl Component {
+    pub fn new(source: &parse::Element) -> Result<Self> {
+        let name = source.attributes.iter()
+            .find(|i| i.name == "name")
+            .map(|i| i.value.to_lowercase())
+            .ok_or_else(|| Error::BuildError(BuildError::MissingAttribute {
+                tag: source.name.clone(),
+                attribute: "name".to_owned(),
+                origin: source.origin.clone(),
+            }))?;
+
+        if BUILTIN_ELEMENTS.contains(&name.as_str()) {
+            return Err(Error::BuildError(BuildError::InvalidAttribute {
+                tag: source.name.clone(),
+                attribute: "name".to_owned(),
+                value: name,
+                origin: source.attributes.iter().find(|i| i.name == "name").map_or(source.origin.clone(), |i| i.origin.clone()),
+            }));
+        }
+
+        Ok(Self {
+            name,
+            props: source.attributes.iter()
+                .filter(|i| i.name != "name")
+                .cloned()
+                .collect(),
+            source: source.clone(),
+        })
+    }
+}
+
+/// Tags with their own behaviour, which can therefore not be used as component names
+pub const BUILTIN_ELEMENTS: [&str; 9] = ["block", "template", "include", "component", "escape", "condition", "fragment", "page", "slot"];
+
+/// Definitions which can be referenced by name from any page
+#[derive(Debug, Default)]
+pub struct Registry {
+    pub templates: HashMap<String, Template>,
+    pub components: HashMap<String, Component>,
+}
+
+impl Registry {
+    /// Collects the definitions of every file in the given directories.
+    /// Directories which don't exist are skipped, as none of them are required.
+    pub async fn scan<Dir: AsRef<Path>>(dirs: impl Iterator<Item=Dir>) -> Result<Self> {
+        let mut registry = Self::default();
+
+        for dir in dirs {
+            if !tokio::fs::try_exists(dir.as_ref()).await? {
+                continue;
+            }
+
+            let dir = tokio::fs::canonicalize(dir.as_ref()).await?;
+            for file in globwalk::GlobWalkerBuilder::from_patterns(&dir, &["**/*.html"]).build()? {
+                let file = file?.into_path();
+                let source = tokio::fs::read_to_string(&file).await?;
+
+                debug!("Scanning {:?} for definitions", &file);
+                registry.register(&ParsingContext::new(source, file)?.parse()?)?;
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /// Adds every definition in the tree to the registry
+    pub fn register(&mut self, element: &parse::Element) -> Result<()> {
+        if element.name == "template" {
+            let template = Template::new(element)?;
+
+            if self.templates.contains_key(&template.name) {
+                return Err(Error::BuildError(BuildError::DuplicateDefinition { name: template.name, origin: element.origin.clone() }));
+            }
+
+            self.templates.insert(template.name.clone(), template);
+            return Ok(());
+        }
+
+        if element.name == "component" {
+            let component = Component::new(element)?;
+
+            if self.components.contains_key(&component.name) {
+                return Err(Error::BuildError(BuildError::DuplicateDefinition { name: component.name, origin: element.origin.clone() }));
+            }
+
+            self.components.insert(component.name.clone(), component);
+            return Ok(());
+        }
+
+        for child in element.body.iter() {
+            if let parse::Body::Element(child) = child {
+                self.register(child)?;
+            }
+        }
+
+        Ok(())
+    }
+}
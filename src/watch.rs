@@ -0,0 +1,193 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use log::{error, info};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use crate::{
+    SITE_ROOT,
+    assets::copy_assets,
+    build::ARGS,
+    build::Dependencies,
+    build::build_pages,
+    build::config,
+    build::list_pages,
+    build::load,
+    error::*,
+    report,
+    report::Format,
+};
+
+/// How long to wait for further changes before rebuilding, as editors tend to write files in several steps
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The files the site is built from
+struct Sources {
+    /// The site config, along with every script it refers to. A change to any of them reloads the whole site.
+    config: Vec<PathBuf>,
+    /// Directories whose definitions are collected into the registry
+    templates: Vec<PathBuf>,
+    /// Directories containing pages
+    roots: Vec<PathBuf>,
+    /// Changes within the build directory are ignored, as they are caused by the build itself
+    build: PathBuf,
+}
+
+impl Sources {
+    fn new() -> Self {
+        let args = ARGS.get().expect("Args not set").clone();
+        let config = config();
+        let site_root = SITE_ROOT.get().expect("Site root not set");
+        let root = site_root.parent().expect("Invalid site root");
+
+        let canonical = |path: PathBuf| path.canonicalize().unwrap_or(path);
+
+        Self {
+            config: std::iter::once(site_root.to_path_buf())
+                .chain(config.content_types.iter()
+                    .filter_map(|content_type| content_type.script.handler_path.as_ref())
+                    .map(|path| canonical(root.join(path))))
+                .collect(),
+            templates: config.templates.iter().map(|dir| canonical(args.root.join(dir))).collect(),
+            roots: config.roots.iter().map(|dir| canonical(args.root.join(dir))).collect(),
+            build: canonical(args.root.join(&config.build)),
+        }
+    }
+
+    /// Starts watching every source which exists, sending the paths of changed files to `tx`
+    fn watch(&self, tx: UnboundedSender<Vec<PathBuf>>) -> Result<RecommendedWatcher> {
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => { let _ = tx.send(event.paths); },
+            Ok(_) => {},
+            Err(err) => error!("Failed to watch for changes: {}", err),
+        })?;
+
+        // Editors often replace files rather than writing to them, which would end a watch on the file itself
+        let dirs = self.config.iter()
+            .filter_map(|path| path.parent())
+            .collect::<HashSet<_>>();
+
+        for dir in dirs {
+            if dir.exists() {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            }
+        }
+
+        for dir in self.templates.iter().chain(self.roots.iter()) {
+            if dir.exists() {
+                watcher.watch(dir, RecursiveMode::Recursive)?;
+            }
+        }
+
+        Ok(watcher)
+    }
+}
+
+/// Waits for a change, then collects every change made shortly after it
+async fn changes(rx: &mut UnboundedReceiver<Vec<PathBuf>>) -> Option<HashSet<PathBuf>> {
+    let mut changed = HashSet::from_iter(rx.recv().await?);
+
+    while let Ok(Some(paths)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+        changed.extend(paths);
+    }
+
+    Some(changed)
+}
+
+/// Rebuilds every page which was affected by the changes, has not been built yet, or failed to build last time.
+/// If `everything` is set, every page is rebuilt regardless.
+/// Returns how many pages changed as a result: pages which were only retried are counted once they build, so a page which keeps failing doesn't count on every change.
+async fn rebuild(changed: &HashSet<PathBuf>, everything: bool, dependencies: &mut Dependencies, failed: &mut HashSet<PathBuf>) -> Result<usize> {
+    let affected = |path: &PathBuf| everything
+        || changed.contains(path)
+        || dependencies.get(path).is_some_and(|dependencies| !dependencies.is_disjoint(changed));
+
+    let pages = list_pages().await?
+        .filter(|page| affected(&page.path) || failed.contains(&page.path) || !dependencies.contains_key(&page.path))
+        .collect::<Vec<_>>();
+
+    if pages.is_empty() {
+        return Ok(0);
+    }
+
+    let retried = pages.iter()
+        .filter(|page| !affected(&page.path))
+        .map(|page| page.path.clone())
+        .collect::<HashSet<_>>();
+
+    info!("Rebuilding {} pages", pages.len());
+
+    let mut count = 0;
+    for (page, result) in build_pages(pages.into_iter(), false).await? {
+        match result {
+            Ok(page_dependencies) => {
+                count += 1;
+                failed.remove(&page);
+                dependencies.insert(page, page_dependencies);
+            },
+            Err(err) => {
+                if !retried.contains(&page) {
+                    count += 1;
+                }
+
+                report::error(&page, &err);
+                failed.insert(page);
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Rebuilds the pages affected by each change to the site's sources, until interrupted.
+/// Changes to the config reload the entire site, while changes to templates reload the registry before rebuilding the pages which use them.
+/// `on_rebuild` is called with the changed files and the number of pages rebuilt once each rebuild has finished.
+/// Copied assets count as rebuilt pages, except for stylesheets, which can be swapped without reloading the pages using them.
+pub async fn watch(mut dependencies: Dependencies, on_rebuild: impl Fn(&HashSet<PathBuf>, usize)) -> Result<()> {
+    let (tx, mut rx) = unbounded_channel();
+    let mut failed = HashSet::new();
+
+    let mut sources = Sources::new();
+    let mut watcher = sources.watch(tx.clone())?;
+
+    info!("Watching for changes");
+
+    while let Some(changed) = changes(&mut rx).await {
+        let changed = changed.into_iter()
+            .filter(|path| !path.starts_with(&sources.build))
+            .collect::<HashSet<_>>();
+
+        let within = |dirs: &[PathBuf]| changed.iter().any(|path| dirs.iter().any(|dir| path.starts_with(dir)));
+        let reload = changed.iter().any(|path| sources.config.contains(path));
+
+        if reload || within(&sources.templates) {
+            if let Err(err) = load().await {
+                error!("Failed to reload the site: {}", err);
+                continue;
+            }
+        }
+
+        if reload {
+            sources = Sources::new();
+            drop(watcher);
+            watcher = sources.watch(tx.clone())?;
+        }
+
+        let assets = match copy_assets().await {
+            Ok(copied) => copied.iter().filter(|path| path.extension().is_none_or(|ext| ext != "css")).count(),
+            Err(err) => {
+                error!("Failed to copy assets: {}", err);
+                0
+            }
+        };
+
+        match rebuild(&changed, reload, &mut dependencies, &mut failed).await {
+            Ok(count) => on_rebuild(&changed, count + assets),
+            Err(err) => error!("Failed to rebuild: {}", err),
+        }
+
+        report::summarise(Format::Human);
+    }
+
+    Ok(())
+}
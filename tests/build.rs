@@ -0,0 +1,109 @@
+use std::path::Path;
+use std::process::Output;
+use tempfile::TempDir;
+
+/// A site of two pages, one of which never closes its `<div>`. It is removed once dropped.
+fn site() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    std::fs::create_dir_all(root.join("www")).unwrap();
+
+    std::fs::write(root.join("site.toml"), r#"
+        roots = ["www"]
+
+        [[language]]
+        abbreviation = "en"
+        full-name = "English"
+        menu = []
+
+        [[page]]
+        name = "good"
+
+        [[page]]
+        name = "bad"
+    "#).unwrap();
+    std::fs::write(root.join("www/good.html"), r#"<page>"Hello"</page>"#).unwrap();
+    std::fs::write(root.join("www/bad.html"), r#"<page><div>"x"</page>"#).unwrap();
+
+    dir
+}
+
+fn run(root: &Path, args: &[&str]) -> Output {
+    std::process::Command::new(env!("CARGO_BIN_EXE_jcake-ssg"))
+        .args(args)
+        .current_dir(root)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_build_all() {
+    let dir = site();
+    let root = dir.path();
+    let good = root.join("build/en/good.html");
+
+    // Every page is attempted, and each failure is reported against its page
+    let output = run(root, &["build"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("www/bad.html:\nerror: `<div>` is never closed\n --> www/bad.html:1:7"), "{}", stderr);
+    assert!(stderr.contains("1 error and 0 warnings in 1 file"), "{}", stderr);
+    assert!(std::fs::read_to_string(&good).unwrap().contains("Hello"));
+    assert!(!root.join("build/en/bad.html").exists());
+
+    // Checking writes nothing, but finds the same problem
+    std::fs::remove_dir_all(root.join("build")).unwrap();
+    let output = run(root, &["check", "--format", "json"]);
+    let problems = serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(problems.as_array().unwrap().len(), 1);
+    assert_eq!(problems[0]["file"], "www/bad.html");
+    assert_eq!(problems[0]["message"], "`<div>` is never closed");
+    assert!(!good.exists());
+
+    // The first failure stops the build, and is the only error printed
+    let output = run(root, &["--fail-fast", "build"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("error: `<div>` is never closed"), "{}", stderr);
+    assert!(!stderr.contains("1 error"), "{}", stderr);
+
+    let output = run(root, &["--fail-fast", "check"]);
+    assert!(!output.status.success());
+
+    std::fs::write(root.join("www/bad.html"), r#"<page><div>"x"</div></page>"#).unwrap();
+    assert!(run(root, &["build"]).status.success());
+    assert!(run(root, &["check"]).status.success());
+    assert!(root.join("build/en/bad.html").exists());
+}
+
+#[test]
+fn test_check_unreadable() {
+    let dir = site();
+    let root = dir.path();
+
+    // A directory where the page should be passes for the page, but can't be read
+    std::fs::remove_file(root.join("www/bad.html")).unwrap();
+    std::fs::create_dir(root.join("www/bad.html")).unwrap();
+
+    let output = run(root, &["check", "--format", "json"]);
+    let problems = serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(problems.as_array().unwrap().len(), 1, "{}", problems);
+    assert_eq!(problems[0]["file"], "www/bad.html");
+}
+
+#[test]
+fn test_check_ambiguous() {
+    let dir = site();
+    let root = dir.path();
+
+    // Both files claim the English `good` page, which is reported once however many passes list the pages
+    std::fs::write(root.join("www/good.en.html"), r#"<page>"Hello"</page>"#).unwrap();
+    std::fs::write(root.join("www/bad.html"), r#"<page><div>"x"</div></page>"#).unwrap();
+
+    let output = run(root, &["check", "--format", "json"]);
+    let problems = serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+    assert_eq!(problems.as_array().unwrap().len(), 1, "{}", problems);
+    assert!(problems[0]["message"].as_str().unwrap().starts_with("ambiguous page name: 2 files"), "{}", problems);
+}